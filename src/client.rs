@@ -4,6 +4,7 @@ use std::os::unix::net::UnixStream;
 use crate::error::{Error, Result};
 use crate::protocol::{Request, Response};
 use crate::server::socket_path;
+use crate::session::ExitStatus;
 
 pub fn send_request(session_id: &str, request: &Request) -> Result<Response> {
     let sock = socket_path(session_id);
@@ -36,6 +37,53 @@ pub fn print_response(response: &Response) {
         Response::Diff { diff } => {
             println!("{}", serde_json::to_string_pretty(diff).unwrap());
         }
+        Response::Match { text, .. } => println!("{}", text),
+        Response::Locations { locations } => {
+            for l in locations {
+                println!("row: {}, cols: {}..{}, text: {:?}", l.row, l.start_col, l.end_col, l.text);
+            }
+        }
+        Response::Status { running: true, pid, .. } => match pid {
+            Some(pid) => println!("running (pid {})", pid),
+            None => println!("running"),
+        },
+        Response::Status { exit, .. } => match exit {
+            Some(ExitStatus { signal: Some(signal), .. }) => println!("exited: {}", signal),
+            Some(ExitStatus { code, .. }) => println!("exited: code {}", code),
+            None => println!("exited"),
+        },
+        Response::Modes { modes } => {
+            let flag = |on: bool| if on { "on" } else { "off" };
+            println!("alternate-screen: {}", flag(modes.alternate_screen));
+            println!("application-cursor: {}", flag(modes.application_cursor));
+            println!("application-keypad: {}", flag(modes.application_keypad));
+            println!("bracketed-paste: {}", flag(modes.bracketed_paste));
+            println!("cursor: {}", if modes.cursor_visible { "visible" } else { "hidden" });
+            println!("mouse: {} ({})", modes.mouse_mode, modes.mouse_encoding);
+            println!("focus-events: {}", flag(modes.focus_events));
+            println!("kitty-keyboard-flags: {}", modes.keyboard_flags);
+            println!("modify-other-keys: {}", modes.modify_other_keys);
+        }
+        Response::Teardown { report } => {
+            for p in &report.terminated {
+                println!("terminated: {} {}", p.pid, p.command);
+            }
+            for p in &report.killed {
+                println!("force-killed: {} {}", p.pid, p.command);
+            }
+        }
+        Response::ProcessInfo { info } => {
+            if let Some(pid) = info.pid {
+                println!("pid: {}", pid);
+            }
+            if let Some(pgrp) = info.foreground_pgrp {
+                println!("foreground: {} {}", pgrp, info.foreground_command.as_deref().unwrap_or(""));
+            }
+            if let Some(t) = &info.termios {
+                let mode = if t.canonical { "canonical" } else if t.raw() { "raw" } else { "cbreak" };
+                println!("mode: {}, echo {}", mode, if t.echo { "on" } else { "off" });
+            }
+        }
         Response::Error { message } => {
             eprintln!("Error: {}", message);
             std::process::exit(1);
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::screen::{self, Region, ScreenSnapshot};
+use crate::terminal::Terminal;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Condition {
+    pub text: String,
+    #[serde(default)]
+    pub regex: bool,
+    // Satisfied when the pattern is absent (or appears a different number of times).
+    #[serde(default)]
+    pub negate: bool,
+    // Require exactly this many non-overlapping matches instead of at least one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+    #[serde(default)]
+    pub scrollback: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchResult {
+    pub matched: bool,
+    pub count: usize,
+    pub matches: Vec<Match>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Match {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Option<String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub named: BTreeMap<String, String>,
+}
+
+// Where a match sits on screen. `end_col` is exclusive and covers both halves
+// of a wide character.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub row: u16,
+    pub start_col: u16,
+    pub end_col: u16,
+    pub text: String,
+}
+
+impl Location {
+    pub fn center(&self) -> (u16, u16) {
+        let col = self.start_col + (self.end_col.saturating_sub(self.start_col + 1)) / 2;
+        (col, self.row)
+    }
+}
+
+impl Condition {
+    pub fn text(text: &str) -> Self {
+        Condition { text: text.to_string(), ..Default::default() }
+    }
+
+    pub fn regex(pattern: &str) -> Self {
+        Condition { text: pattern.to_string(), regex: true, ..Default::default() }
+    }
+
+    pub fn compile(&self) -> Result<Matcher> {
+        let regex = if self.regex {
+            Some(Regex::new(&self.text).map_err(|e| Error::InvalidPattern(e.to_string()))?)
+        } else {
+            None
+        };
+        Ok(Matcher { condition: self.clone(), regex })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negate {
+            write!(f, "no ")?;
+        }
+        if self.regex {
+            write!(f, "/{}/", self.text)?;
+        } else {
+            write!(f, "{:?}", self.text)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, " x{}", count)?;
+        }
+        if let Some(r) = &self.region {
+            write!(f, " in {},{}..{},{}", r.top, r.left, r.bottom, r.right)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Matcher {
+    condition: Condition,
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    pub fn condition(&self) -> &Condition {
+        &self.condition
+    }
+
+    pub fn evaluate(&self, haystack: &str) -> MatchResult {
+        let matches: Vec<Match> = match &self.regex {
+            Some(re) => re
+                .captures_iter(haystack)
+                .map(|caps| Match {
+                    text: caps[0].to_string(),
+                    groups: caps.iter().skip(1).map(|g| g.map(|m| m.as_str().to_string())).collect(),
+                    named: re
+                        .capture_names()
+                        .flatten()
+                        .filter_map(|name| caps.name(name).map(|m| (name.to_string(), m.as_str().to_string())))
+                        .collect(),
+                })
+                .collect(),
+            None => haystack
+                .matches(self.condition.text.as_str())
+                .map(|m| Match { text: m.to_string(), groups: Vec::new(), named: BTreeMap::new() })
+                .collect(),
+        };
+        let count = matches.len();
+        let found = match self.condition.count {
+            Some(expected) => count == expected,
+            None => count > 0,
+        };
+        MatchResult { matched: found != self.condition.negate, count, matches }
+    }
+
+    pub fn evaluate_snapshot(&self, snapshot: &ScreenSnapshot) -> MatchResult {
+        self.evaluate(&self.snapshot_text(snapshot))
+    }
+
+    // Returns the text the condition was evaluated against alongside the result.
+    pub fn check(&self, terminal: &mut Terminal) -> (String, MatchResult) {
+        let haystack = match &self.condition.region {
+            Some(_) => self.snapshot_text(&terminal.snapshot()),
+            None => terminal.search_text(self.condition.scrollback),
+        };
+        let result = self.evaluate(&haystack);
+        (haystack, result)
+    }
+
+    // Finds every match within a single row, mapping byte offsets back to cell
+    // columns so wide characters are accounted for.
+    pub fn locate(&self, snapshot: &ScreenSnapshot) -> Vec<Location> {
+        let region = self.condition.region.unwrap_or(Region::rows(0, u16::MAX));
+        let mut locations = Vec::new();
+        for (row, cells) in snapshot.cells.iter().enumerate() {
+            if !(region.top as usize..=region.bottom as usize).contains(&row) {
+                continue;
+            }
+            let mut line = String::new();
+            // (byte offset in line, first column, column after the cell)
+            let mut spans: Vec<(usize, u16, u16)> = Vec::new();
+            for (col, cell) in cells.iter().enumerate() {
+                if cell.wide_continuation || !(region.left as usize..=region.right as usize).contains(&col) {
+                    continue;
+                }
+                let width = if cell.wide { 2 } else { 1 };
+                spans.push((line.len(), col as u16, col as u16 + width));
+                line.push_str(if cell.char.is_empty() { " " } else { &cell.char });
+            }
+            for range in self.ranges(&line) {
+                let first = spans.partition_point(|&(offset, _, _)| offset <= range.start) - 1;
+                let last = spans.partition_point(|&(offset, _, _)| offset < range.end) - 1;
+                locations.push(Location {
+                    row: row as u16,
+                    start_col: spans[first].1,
+                    end_col: spans[last].2,
+                    text: line[range].to_string(),
+                });
+            }
+        }
+        locations
+    }
+
+    fn ranges(&self, haystack: &str) -> Vec<Range<usize>> {
+        let ranges: Vec<Range<usize>> = match &self.regex {
+            Some(re) => re.find_iter(haystack).map(|m| m.range()).collect(),
+            None => haystack
+                .match_indices(self.condition.text.as_str())
+                .map(|(start, m)| start..start + m.len())
+                .collect(),
+        };
+        ranges.into_iter().filter(|r| !r.is_empty()).collect()
+    }
+
+    fn snapshot_text(&self, snapshot: &ScreenSnapshot) -> String {
+        let region = self.condition.region.unwrap_or(Region::rows(0, u16::MAX));
+        screen::region_text(snapshot, &region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: &str = "Files: 3\n  [x] a.txt\n  [ ] b.txt\n  [x] c.txt";
+
+    #[test]
+    fn test_text_condition() {
+        let matcher = Condition::text("b.txt").compile().unwrap();
+        let result = matcher.evaluate(SCREEN);
+        assert!(result.matched);
+        assert_eq!(result.count, 1);
+        assert!(!matcher.evaluate("nothing").matched);
+    }
+
+    #[test]
+    fn test_negated_condition() {
+        let matcher = Condition { negate: true, ..Condition::text("Loading") }.compile().unwrap();
+        assert!(matcher.evaluate(SCREEN).matched);
+        assert!(!matcher.evaluate("Loading...").matched);
+    }
+
+    #[test]
+    fn test_count_condition() {
+        let matcher = Condition { count: Some(2), ..Condition::text("[x]") }.compile().unwrap();
+        assert!(matcher.evaluate(SCREEN).matched);
+        let matcher = Condition { count: Some(3), ..Condition::text("[x]") }.compile().unwrap();
+        let result = matcher.evaluate(SCREEN);
+        assert!(!result.matched);
+        assert_eq!(result.count, 2);
+    }
+
+    #[test]
+    fn test_regex_captures() {
+        let matcher = Condition::regex(r"Files: (?P<n>\d+)|\[x\] (\w+)").compile().unwrap();
+        let result = matcher.evaluate(SCREEN);
+        assert_eq!(result.count, 3);
+        assert_eq!(result.matches[0].text, "Files: 3");
+        assert_eq!(result.matches[0].groups, vec![Some("3".to_string()), None]);
+        assert_eq!(result.matches[0].named["n"], "3");
+        assert_eq!(result.matches[2].groups, vec![None, Some("c".to_string())]);
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        assert!(matches!(Condition::regex("(").compile(), Err(Error::InvalidPattern(_))));
+    }
+
+    #[test]
+    fn test_region_condition() {
+        let mut parser = vt100::Parser::new(4, 20, 0);
+        parser.process(b"title\r\nleft     right");
+        let snapshot = screen::from_screen(parser.screen());
+        let anywhere = Condition::text("right").compile().unwrap();
+        assert!(anywhere.evaluate_snapshot(&snapshot).matched);
+        let first_row = Condition { region: Some(Region::rows(0, 0)), ..Condition::text("right") };
+        assert!(!first_row.compile().unwrap().evaluate_snapshot(&snapshot).matched);
+        let left_half = Condition { region: Some(Region { top: 0, left: 0, bottom: 3, right: 7 }), ..Condition::text("right") };
+        assert!(!left_half.compile().unwrap().evaluate_snapshot(&snapshot).matched);
+    }
+
+    #[test]
+    fn test_locate() {
+        let mut parser = vt100::Parser::new(4, 20, 0);
+        parser.process("  OK  Cancel\r\n\u{4e2d}\u{6587} OK".as_bytes());
+        let snapshot = screen::from_screen(parser.screen());
+        let found = Condition::text("OK").compile().unwrap().locate(&snapshot);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].row, found[0].start_col, found[0].end_col), (0, 2, 4));
+        assert_eq!((found[1].row, found[1].start_col, found[1].end_col), (1, 5, 7));
+
+        let found = Condition::regex("\u{6587}.").compile().unwrap().locate(&snapshot);
+        assert_eq!((found[0].start_col, found[0].end_col), (2, 5));
+        assert_eq!(found[0].text, "\u{6587} ");
+
+        let cancel = Condition::text("Cancel").compile().unwrap().locate(&snapshot);
+        assert_eq!(cancel[0].center(), (8, 0));
+    }
+
+    #[test]
+    fn test_condition_wire_format() {
+        let condition: Condition = serde_json::from_str(r#"{"text":"ok"}"#).unwrap();
+        assert!(!condition.regex && !condition.negate && !condition.scrollback);
+        assert_eq!(condition.to_string(), "\"ok\"");
+        let condition = Condition { negate: true, count: Some(2), ..Condition::regex("a+") };
+        assert_eq!(condition.to_string(), "no /a+/ x2");
+    }
+}
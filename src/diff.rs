@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::screen::{CellInfo, ColorInfo, ScreenSnapshot};
+use crate::screen::{CellInfo, ColorCompare, ColorInfo, ColorKind, ScreenSnapshot};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnapshotDiff {
@@ -48,16 +48,30 @@ pub struct DiffSummary {
 fn empty_cell() -> CellInfo {
     CellInfo {
         char: " ".to_string(),
-        fg: ColorInfo { r: 255, g: 255, b: 255 },
-        bg: ColorInfo { r: 0, g: 0, b: 0 },
+        fg: ColorInfo { kind: ColorKind::Default, ..ColorInfo::rgb(255, 255, 255) },
+        bg: ColorInfo { kind: ColorKind::Default, ..ColorInfo::rgb(0, 0, 0) },
         bold: false,
         italic: false,
         underline: false,
         inverse: false,
+        dim: false,
+        blink: false,
+        strikethrough: false,
+        wide: false,
+        wide_continuation: false,
+        hyperlink: None,
     }
 }
 
 pub fn compute_diff(baseline: &ScreenSnapshot, current: &ScreenSnapshot) -> SnapshotDiff {
+    compute_diff_with(baseline, current, ColorCompare::default())
+}
+
+pub fn compute_diff_with(
+    baseline: &ScreenSnapshot,
+    current: &ScreenSnapshot,
+    colors: ColorCompare,
+) -> SnapshotDiff {
     let dimensions_changed = if baseline.rows != current.rows || baseline.cols != current.cols {
         Some(DimensionChange {
             old_rows: baseline.rows,
@@ -90,7 +104,7 @@ pub fn compute_diff(baseline: &ScreenSnapshot, current: &ScreenSnapshot) -> Snap
         for col in 0..compare_cols {
             let old_cell = &baseline.cells[row][col];
             let new_cell = &current.cells[row][col];
-            if old_cell != new_cell {
+            if !old_cell.matches(new_cell, colors) {
                 changed_cells.push(CellChange {
                     row: row as u16,
                     col: col as u16,
@@ -197,7 +211,7 @@ mod tests {
         let diff = compute_diff(&snap1, &snap2);
         assert!(!diff.identical);
         assert!(diff.dimensions_changed.is_none());
-        assert!(diff.changed_cells.len() > 0);
+        assert!(!diff.changed_cells.is_empty());
     }
 
     #[test]
@@ -238,6 +252,24 @@ mod tests {
         assert_eq!(dims.new_cols, 12);
     }
 
+    #[test]
+    fn test_color_compare_modes() {
+        let mut parser1 = vt100::Parser::new(4, 10, 0);
+        parser1.process(b"\x1b[31mred");
+        let snap1 = screen::from_screen(parser1.screen());
+
+        let mut parser2 = vt100::Parser::new(4, 10, 0);
+        parser2.process(b"\x1b[38;2;205;0;0mred");
+        let snap2 = screen::from_screen(parser2.screen());
+
+        let resolved = compute_diff_with(&snap1, &snap2, ColorCompare::Resolved);
+        assert!(resolved.identical);
+
+        let semantic = compute_diff_with(&snap1, &snap2, ColorCompare::Semantic);
+        assert!(!semantic.identical);
+        assert_eq!(semantic.changed_cells.len(), 3);
+    }
+
     #[test]
     fn test_diff_serialization() {
         let mut parser = vt100::Parser::new(4, 10, 0);
@@ -20,11 +20,26 @@ pub enum Error {
     #[error("Unknown mouse action: {0}")]
     UnknownMouseAction(String),
 
+    #[error("Unknown signal: {0}")]
+    UnknownSignal(String),
+
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("{0} not found on screen")]
+    NotFound(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
     #[error("Protocol error: {0}")]
     Protocol(String),
 
     #[error("Child process exited")]
     ChildExited,
+
+    #[error("Timed out after {0}ms")]
+    Timeout(u64),
 }
 
 impl From<anyhow::Error> for Error {
@@ -1,7 +1,13 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
 use crate::error::{Error, Result};
+use crate::screen::{MouseEncoding, MouseMode, TerminalModes};
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Key {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
     Char(char),
     Enter,
     Tab,
@@ -18,135 +24,755 @@ pub enum Key {
     Insert,
     Delete,
     F(u8),
-    Ctrl(char),
-    Alt(char),
+    // Numeric keypad: digits, + - * / . and Enter as '\r'.
+    Keypad(char),
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum MouseAction {
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+    pub meta: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, alt: false, ctrl: false, meta: false };
+    pub const SHIFT: Modifiers = Modifiers { shift: true, ..Modifiers::NONE };
+    pub const ALT: Modifiers = Modifiers { alt: true, ..Modifiers::NONE };
+    pub const CTRL: Modifiers = Modifiers { ctrl: true, ..Modifiers::NONE };
+
+    pub fn is_empty(&self) -> bool {
+        *self == Modifiers::NONE
+    }
+
+    // The modifier parameter xterm appends to CSI sequences, e.g. 5 in CSI 1;5A.
+    pub fn xterm_param(&self) -> u8 {
+        1 + self.shift as u8 + 2 * self.alt as u8 + 4 * self.ctrl as u8 + 8 * self.meta as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl Key {
+    pub const fn new(code: KeyCode) -> Self {
+        Key { code, modifiers: Modifiers::NONE }
+    }
+
+    pub const fn with(code: KeyCode, modifiers: Modifiers) -> Self {
+        Key { code, modifiers }
+    }
+}
+
+impl From<KeyCode> for Key {
+    fn from(code: KeyCode) -> Self {
+        Key::new(code)
+    }
+}
+
+// How the cursor keys and the numeric keypad are encoded, switched by the app
+// with DECCKM (CSI ? 1 h) and DECKPAM (ESC =).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyEncoding {
+    #[default]
+    Normal,
+    Application,
+}
+
+// Kitty progressive keyboard enhancement flags (CSI > flags u).
+pub const KITTY_DISAMBIGUATE: u8 = 1;
+pub const KITTY_REPORT_EVENTS: u8 = 2;
+pub const KITTY_REPORT_ALTERNATES: u8 = 4;
+pub const KITTY_REPORT_ALL_KEYS: u8 = 8;
+pub const KITTY_REPORT_TEXT: u8 = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyEvent {
+    #[default]
     Press,
+    Repeat,
     Release,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyModes {
+    pub cursor: KeyEncoding,
+    pub keypad: KeyEncoding,
+    // Kitty keyboard flags in effect; 0 selects the legacy encodings.
+    pub kitty_flags: u8,
+    // xterm modifyOtherKeys level (CSI > 4 ; level m).
+    pub modify_other_keys: u8,
+}
+
+impl From<&TerminalModes> for KeyModes {
+    fn from(modes: &TerminalModes) -> Self {
+        let encoding = |application| if application { KeyEncoding::Application } else { KeyEncoding::Normal };
+        KeyModes {
+            cursor: encoding(modes.application_cursor),
+            keypad: encoding(modes.application_keypad),
+            kitty_flags: modes.keyboard_flags,
+            modify_other_keys: modes.modify_other_keys,
+        }
+    }
+}
+
+// Forces an encoding regardless of what the app asked for; unset fields follow
+// the terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<KeyEncoding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keypad: Option<KeyEncoding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kitty_flags: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modify_other_keys: Option<u8>,
+}
+
+impl KeyOverride {
+    pub fn apply(&self, modes: KeyModes) -> KeyModes {
+        KeyModes {
+            cursor: self.cursor.unwrap_or(modes.cursor),
+            keypad: self.keypad.unwrap_or(modes.keypad),
+            kitty_flags: self.kitty_flags.unwrap_or(modes.kitty_flags),
+            modify_other_keys: self.modify_other_keys.unwrap_or(modes.modify_other_keys),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    #[default]
+    Left,
+    Middle,
+    Right,
+}
+
+impl fmt::Display for MouseButton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MouseButton::Left => "left",
+            MouseButton::Middle => "middle",
+            MouseButton::Right => "right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+    // Motion with no button held.
     Move,
+    // Motion with a button held.
+    Drag(MouseButton),
     ScrollUp,
     ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    pub col: u16,
+    pub row: u16,
+    pub modifiers: Modifiers,
 }
 
 impl Key {
     pub fn to_escape_sequence(&self) -> Vec<u8> {
-        match self {
-            Key::Char(c) => {
-                let mut buf = [0u8; 4];
-                let s = c.encode_utf8(&mut buf);
-                s.as_bytes().to_vec()
+        self.encode(KeyModes::default())
+    }
+
+    pub fn encode(&self, modes: KeyModes) -> Vec<u8> {
+        self.encode_event(KeyEvent::Press, modes)
+    }
+
+    // Legacy encodings cannot express key releases, so those come out empty
+    // unless the app has asked for kitty event reporting.
+    pub fn encode_event(&self, event: KeyEvent, modes: KeyModes) -> Vec<u8> {
+        if modes.kitty_flags != 0 {
+            return self.encode_kitty(event, modes.kitty_flags);
+        }
+        if event == KeyEvent::Release {
+            return Vec::new();
+        }
+        self.encode_modify_other_keys(modes.modify_other_keys)
+            .unwrap_or_else(|| self.encode_legacy(modes))
+    }
+
+    // Like `encode_event`, but refuses keys whose Ctrl the legacy encodings
+    // would silently drop, such as ctrl+1 or ctrl+enter. modifyOtherKeys and
+    // the kitty protocol can express those.
+    pub fn try_encode(&self, event: KeyEvent, modes: KeyModes) -> Result<Vec<u8>> {
+        let extended = modes.kitty_flags != 0 || modes.modify_other_keys != 0;
+        if self.modifiers.ctrl && !extended && !self.has_ctrl_form() {
+            return Err(Error::UnknownKey(self.to_string()));
+        }
+        Ok(self.encode_event(event, modes))
+    }
+
+    fn has_ctrl_form(&self) -> bool {
+        match self.code {
+            KeyCode::Char(c) => control_byte(c).is_some(),
+            KeyCode::Tab => self.modifiers.shift,
+            KeyCode::Enter | KeyCode::Escape => false,
+            _ => true,
+        }
+    }
+
+    // Follows xterm's defaults: modified cursor, editing and function keys
+    // carry the modifier as a CSI parameter, while Alt (and Meta) on anything
+    // else is sent as an ESC prefix.
+    fn encode_legacy(&self, modes: KeyModes) -> Vec<u8> {
+        let mods = self.modifiers;
+        let param = mods.xterm_param();
+        let esc_prefix = mods.alt || mods.meta;
+        let plain = match self.code {
+            KeyCode::Char(c) => char_bytes(c, mods),
+            KeyCode::Enter => vec![b'\r'],
+            KeyCode::Tab if mods == Modifiers::SHIFT => return b"\x1b[Z".to_vec(),
+            KeyCode::Tab if mods.shift => return format!("\x1b[1;{}Z", param).into_bytes(),
+            KeyCode::Tab => vec![b'\t'],
+            KeyCode::Backspace if mods.ctrl => vec![0x08],
+            KeyCode::Backspace => vec![0x7f],
+            KeyCode::Escape => vec![0x1b],
+            KeyCode::Up => return cursor_key(b'A', param, modes.cursor),
+            KeyCode::Down => return cursor_key(b'B', param, modes.cursor),
+            KeyCode::Right => return cursor_key(b'C', param, modes.cursor),
+            KeyCode::Left => return cursor_key(b'D', param, modes.cursor),
+            KeyCode::Home => return cursor_key(b'H', param, modes.cursor),
+            KeyCode::End => return cursor_key(b'F', param, modes.cursor),
+            KeyCode::Insert => return tilde_key(2, param),
+            KeyCode::Delete => return tilde_key(3, param),
+            KeyCode::PageUp => return tilde_key(5, param),
+            KeyCode::PageDown => return tilde_key(6, param),
+            // F13-F24 are shifted F1-F12, as in xterm's terminfo (kf13=\E[1;2P).
+            KeyCode::F(n @ 13..=24) => {
+                let shifted = Modifiers { shift: true, ..mods };
+                return Key::with(KeyCode::F(n - 12), shifted).encode_legacy(modes);
+            }
+            KeyCode::F(n @ 1..=4) => {
+                let code = b'P' + (n - 1);
+                return match param {
+                    1 => vec![0x1b, b'O', code],
+                    _ => format!("\x1b[1;{}{}", param, code as char).into_bytes(),
+                };
             }
-            Key::Enter => vec![13],
-            Key::Tab => vec![9],
-            Key::Backspace => vec![127],
-            Key::Escape => vec![27],
-            Key::Up => b"\x1b[A".to_vec(),
-            Key::Down => b"\x1b[B".to_vec(),
-            Key::Left => b"\x1b[D".to_vec(),
-            Key::Right => b"\x1b[C".to_vec(),
-            Key::Home => b"\x1b[H".to_vec(),
-            Key::End => b"\x1b[F".to_vec(),
-            Key::PageUp => b"\x1b[5~".to_vec(),
-            Key::PageDown => b"\x1b[6~".to_vec(),
-            Key::Insert => b"\x1b[2~".to_vec(),
-            Key::Delete => b"\x1b[3~".to_vec(),
-            Key::F(n) => match n {
-                1 => b"\x1bOP".to_vec(),
-                2 => b"\x1bOQ".to_vec(),
-                3 => b"\x1bOR".to_vec(),
-                4 => b"\x1bOS".to_vec(),
-                5 => b"\x1b[15~".to_vec(),
-                6 => b"\x1b[17~".to_vec(),
-                7 => b"\x1b[18~".to_vec(),
-                8 => b"\x1b[19~".to_vec(),
-                9 => b"\x1b[20~".to_vec(),
-                10 => b"\x1b[21~".to_vec(),
-                11 => b"\x1b[23~".to_vec(),
-                12 => b"\x1b[24~".to_vec(),
-                _ => vec![],
+            KeyCode::F(n @ 5..=12) => return tilde_key(F5_TO_F12[n as usize - 5], param),
+            KeyCode::F(_) => return Vec::new(),
+            KeyCode::Keypad(c) => match modes.keypad {
+                KeyEncoding::Normal => vec![c as u8],
+                KeyEncoding::Application => keypad_application(c),
             },
-            Key::Ctrl(c) => {
-                let byte = (*c as u8).wrapping_sub(b'a').wrapping_add(1);
-                vec![byte]
+        };
+        if esc_prefix {
+            let mut seq = vec![0x1b];
+            seq.extend(plain);
+            seq
+        } else {
+            plain
+        }
+    }
+
+    // xterm's CSI 27 ; mod ; code ~ form. Level 1 only uses it where the
+    // legacy bytes would be ambiguous (Ctrl+Shift+A, Ctrl+Enter, Ctrl+1);
+    // level 2 uses it for every modified key that is not just shifted text.
+    fn encode_modify_other_keys(&self, level: u8) -> Option<Vec<u8>> {
+        let mods = self.modifiers;
+        let code = match self.code {
+            KeyCode::Char(c) if mods.shift => c.to_ascii_uppercase(),
+            KeyCode::Char(c) => c,
+            KeyCode::Enter => '\r',
+            KeyCode::Tab => '\t',
+            KeyCode::Backspace => '\x7f',
+            KeyCode::Escape => '\x1b',
+            _ => return None,
+        };
+        let applies = match (level, self.code) {
+            (0, _) => false,
+            (1, KeyCode::Char(c)) => mods.ctrl && (mods.shift || control_byte(c).is_none()),
+            (1, KeyCode::Tab) => mods.ctrl,
+            (1, _) => mods.ctrl || mods.shift,
+            (_, KeyCode::Char(_) | KeyCode::Tab) => !mods.is_empty() && mods != Modifiers::SHIFT,
+            _ => !mods.is_empty(),
+        };
+        applies.then(|| format!("\x1b[27;{};{}~", mods.xterm_param(), code as u32).into_bytes())
+    }
+
+    // Kitty's CSI key ; modifiers:event ; text u. Keys that produce text and
+    // unmodified Enter, Tab and Backspace keep their legacy bytes unless every
+    // key is to be reported as an escape code.
+    fn encode_kitty(&self, event: KeyEvent, flags: u8) -> Vec<u8> {
+        let mut mods = self.modifiers;
+        let report_all = flags & KITTY_REPORT_ALL_KEYS != 0;
+        let produces_text = match self.code {
+            KeyCode::Char(_) => true,
+            KeyCode::Keypad(c) => c != '\r',
+            _ => false,
+        } && !mods.ctrl
+            && !mods.alt
+            && !mods.meta;
+        let legacy = !report_all
+            && match self.code {
+                KeyCode::Char(_) | KeyCode::Keypad(_) => produces_text,
+                KeyCode::Enter | KeyCode::Tab | KeyCode::Backspace => mods.is_empty(),
+                _ => false,
+            };
+        if legacy {
+            return match event {
+                KeyEvent::Release => Vec::new(),
+                _ => self.encode_legacy(KeyModes::default()),
+            };
+        }
+        let event_code = match event {
+            _ if flags & KITTY_REPORT_EVENTS == 0 => 1,
+            KeyEvent::Press => 1,
+            KeyEvent::Repeat => 2,
+            KeyEvent::Release => 3,
+        };
+        if event == KeyEvent::Release && event_code == 1 {
+            return Vec::new();
+        }
+
+        let mut text = None;
+        let (number, terminator) = match self.code {
+            KeyCode::Char(c) => {
+                // Keys are identified by their unshifted codepoint.
+                if c.is_ascii_uppercase() {
+                    mods.shift = true;
+                }
+                let base = c.to_ascii_lowercase();
+                let shifted = if mods.shift { c.to_ascii_uppercase() } else { c };
+                if produces_text && event != KeyEvent::Release {
+                    text = Some(shifted);
+                }
+                let mut key = (base as u32).to_string();
+                if flags & KITTY_REPORT_ALTERNATES != 0 && shifted != base {
+                    key = format!("{}:{}", key, shifted as u32);
+                }
+                (key, 'u')
+            }
+            KeyCode::Enter => ("13".to_string(), 'u'),
+            KeyCode::Tab => ("9".to_string(), 'u'),
+            KeyCode::Backspace => ("127".to_string(), 'u'),
+            KeyCode::Escape => ("27".to_string(), 'u'),
+            KeyCode::Up => ("1".to_string(), 'A'),
+            KeyCode::Down => ("1".to_string(), 'B'),
+            KeyCode::Right => ("1".to_string(), 'C'),
+            KeyCode::Left => ("1".to_string(), 'D'),
+            KeyCode::Home => ("1".to_string(), 'H'),
+            KeyCode::End => ("1".to_string(), 'F'),
+            KeyCode::Insert => ("2".to_string(), '~'),
+            KeyCode::Delete => ("3".to_string(), '~'),
+            KeyCode::PageUp => ("5".to_string(), '~'),
+            KeyCode::PageDown => ("6".to_string(), '~'),
+            KeyCode::F(1) => ("1".to_string(), 'P'),
+            KeyCode::F(2) => ("1".to_string(), 'Q'),
+            // CSI R would be confused with a cursor position report.
+            KeyCode::F(3) => ("13".to_string(), '~'),
+            KeyCode::F(4) => ("1".to_string(), 'S'),
+            KeyCode::F(n @ 5..=12) => (F5_TO_F12[n as usize - 5].to_string(), '~'),
+            KeyCode::F(n @ 13..=24) => ((57376 + n as u32 - 13).to_string(), 'u'),
+            KeyCode::F(_) => return Vec::new(),
+            KeyCode::Keypad(c) => {
+                if produces_text && event != KeyEvent::Release {
+                    text = Some(c);
+                }
+                (kitty_keypad_code(c).to_string(), 'u')
             }
-            Key::Alt(c) => {
-                let mut buf = vec![27];
-                let mut char_buf = [0u8; 4];
-                let s = c.encode_utf8(&mut char_buf);
-                buf.extend_from_slice(s.as_bytes());
-                buf
+        };
+
+        let param = mods.xterm_param();
+        let modifiers = match (param, event_code) {
+            (1, 1) => String::new(),
+            (param, 1) => param.to_string(),
+            (param, event) => format!("{}:{}", param, event),
+        };
+        let text = text.filter(|_| report_all && flags & KITTY_REPORT_TEXT != 0);
+        let mut params = match (modifiers.is_empty(), terminator) {
+            (true, 'A'..='Z') => String::new(),
+            _ => number,
+        };
+        if !modifiers.is_empty() || text.is_some() {
+            params = format!("{};{}", params, modifiers);
+        }
+        if let Some(text) = text {
+            params = format!("{};{}", params, text as u32);
+        }
+        format!("\x1b[{}{}", params, terminator).into_bytes()
+    }
+}
+
+// Formats a key the way `parse_key_name` reads it, e.g. "ctrl+shift+up".
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mods = self.modifiers;
+        for (held, name) in [(mods.ctrl, "ctrl"), (mods.alt, "alt"), (mods.shift, "shift"), (mods.meta, "meta")] {
+            if held {
+                write!(f, "{}+", name)?;
             }
         }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::Keypad('\r') => write!(f, "kp-enter"),
+            KeyCode::Keypad(c) => write!(f, "kp{}", c),
+            code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+        }
     }
 }
 
-pub fn parse_key_name(name: &str) -> Result<Key> {
-    let lower = name.to_lowercase();
+const F5_TO_F12: [u8; 8] = [15, 17, 18, 19, 20, 21, 23, 24];
 
-    if lower.starts_with("ctrl+") || lower.starts_with("ctrl-") {
-        let ch = lower[5..].chars().next().ok_or_else(|| Error::UnknownKey(name.to_string()))?;
-        if ch.is_ascii_lowercase() {
-            return Ok(Key::Ctrl(ch));
+fn char_bytes(c: char, mods: Modifiers) -> Vec<u8> {
+    if let Some(byte) = control_byte(c).filter(|_| mods.ctrl) {
+        return vec![byte];
+    }
+    let c = if mods.shift { c.to_ascii_uppercase() } else { c };
+    c.to_string().into_bytes()
+}
+
+// Ctrl folds letters and a handful of punctuation into C0 control bytes the
+// way a US keyboard would; other characters have no Ctrl form.
+fn control_byte(c: char) -> Option<u8> {
+    match c.to_ascii_lowercase() {
+        c @ 'a'..='z' => Some(c as u8 - b'a' + 1),
+        ' ' | '@' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '~' | '6' => Some(0x1e),
+        '_' | '/' | '-' | '7' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+// Kitty reports keypad keys with their own codepoints from the Unicode
+// private use area, starting at KP_0.
+fn kitty_keypad_code(c: char) -> u32 {
+    match c {
+        '0'..='9' => 57399 + (c as u32 - '0' as u32),
+        '.' => 57409,
+        '/' => 57410,
+        '*' => 57411,
+        '-' => 57412,
+        '+' => 57413,
+        _ => 57414,
+    }
+}
+
+// SS3 in application cursor mode, CSI otherwise; modified keys always use the
+// CSI 1;<mod> form.
+fn cursor_key(code: u8, param: u8, encoding: KeyEncoding) -> Vec<u8> {
+    match (param, encoding) {
+        (1, KeyEncoding::Application) => vec![0x1b, b'O', code],
+        (1, KeyEncoding::Normal) => vec![0x1b, b'[', code],
+        _ => format!("\x1b[1;{}{}", param, code as char).into_bytes(),
+    }
+}
+
+fn tilde_key(number: u8, param: u8) -> Vec<u8> {
+    match param {
+        1 => format!("\x1b[{}~", number).into_bytes(),
+        _ => format!("\x1b[{};{}~", number, param).into_bytes(),
+    }
+}
+
+fn keypad_application(c: char) -> Vec<u8> {
+    let code = match c {
+        '0'..='9' => b'p' + (c as u8 - b'0'),
+        '\r' => b'M',
+        '*' => b'j',
+        '+' => b'k',
+        '-' => b'm',
+        '.' => b'n',
+        '/' => b'o',
+        _ => return vec![c as u8],
+    };
+    vec![0x1b, b'O', code]
+}
+
+// Accepts any number of modifier prefixes joined with + or -, e.g. "ctrl+up",
+// "ctrl+shift+left", "alt-enter", "ctrl++", followed by a key name or a
+// single character.
+pub fn parse_key_name(name: &str) -> Result<Key> {
+    let mut modifiers = Modifiers::NONE;
+    let mut rest = name;
+    while let Some((word, tail)) = rest.split_once(['+', '-']) {
+        if tail.is_empty() {
+            break;
+        }
+        match word.to_lowercase().as_str() {
+            "shift" => modifiers.shift = true,
+            "alt" => modifiers.alt = true,
+            "ctrl" | "control" => modifiers.ctrl = true,
+            "meta" => modifiers.meta = true,
+            _ => break,
         }
-        return Err(Error::UnknownKey(name.to_string()));
+        rest = tail;
     }
 
-    if lower.starts_with("alt+") || lower.starts_with("alt-") {
-        let ch = lower[4..].chars().next().ok_or_else(|| Error::UnknownKey(name.to_string()))?;
-        return Ok(Key::Alt(ch));
+    let mut chars = rest.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        let c = if modifiers.is_empty() { c } else { c.to_ascii_lowercase() };
+        return Ok(Key::with(KeyCode::Char(c), modifiers));
     }
+    let code = parse_key_code(&rest.to_lowercase()).ok_or_else(|| Error::UnknownKey(name.to_string()))?;
+    Ok(Key::with(code, modifiers))
+}
 
-    if lower.starts_with('f') && lower.len() >= 2 {
-        if let Ok(n) = lower[1..].parse::<u8>() {
-            if (1..=12).contains(&n) {
-                return Ok(Key::F(n));
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyToken {
+    Key(Key),
+    Text(String),
+}
+
+// Whitespace-separated key names, e.g. "ctrl+x ctrl+s" or "esc : w q enter".
+// Text in single or double quotes is typed as it is, spaces included, with
+// a backslash escaping the next character. Unquoted tokens that cannot be
+// key names, such as ":wq", are typed too, but anything that looks like a
+// misspelt key name is an error.
+pub fn parse_key_sequence(sequence: &str) -> Result<Vec<KeyToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = sequence.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some(q) if q == c => break,
+                    Some('\\') if chars.peek().is_some() => text.extend(chars.next()),
+                    Some(ch) => text.push(ch),
+                    None => return Err(Error::InvalidArgument(format!("unterminated quote in key sequence {:?}", sequence))),
+                }
             }
+            tokens.push(KeyToken::Text(text));
+            continue;
+        }
+        let mut token = String::new();
+        while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace()) {
+            token.push(ch);
         }
+        let token = match parse_key_name(&token) {
+            Ok(key) => KeyToken::Key(key),
+            Err(e) if looks_like_key_name(&token) => return Err(e),
+            Err(_) => KeyToken::Text(token),
+        };
+        tokens.push(token);
     }
+    Ok(tokens)
+}
 
-    match lower.as_str() {
-        "enter" | "return" => Ok(Key::Enter),
-        "tab" => Ok(Key::Tab),
-        "backspace" | "bs" => Ok(Key::Backspace),
-        "escape" | "esc" => Ok(Key::Escape),
-        "up" => Ok(Key::Up),
-        "down" => Ok(Key::Down),
-        "left" => Ok(Key::Left),
-        "right" => Ok(Key::Right),
-        "home" => Ok(Key::Home),
-        "end" => Ok(Key::End),
-        "pageup" | "pgup" => Ok(Key::PageUp),
-        "pagedown" | "pgdn" => Ok(Key::PageDown),
-        "insert" | "ins" => Ok(Key::Insert),
-        "delete" | "del" => Ok(Key::Delete),
-        "space" => Ok(Key::Char(' ')),
-        _ => Err(Error::UnknownKey(name.to_string())),
+fn looks_like_key_name(token: &str) -> bool {
+    let modifier = |word: &str| ["shift", "alt", "ctrl", "control", "meta"].contains(&word.to_lowercase().as_str());
+    token.contains('+')
+        || token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        || token.split_once('-').is_some_and(|(word, _)| modifier(word))
+}
+
+// Quotes `text` so parse_key_sequence types it literally.
+pub fn quote_key_text(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn parse_key_code(lower: &str) -> Option<KeyCode> {
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return (1..=24).contains(&n).then_some(KeyCode::F(n));
+    }
+
+    if let Some(rest) = lower.strip_prefix("kp").map(|r| r.trim_start_matches(['-', '_'])) {
+        let c = match rest {
+            "enter" => '\r',
+            "plus" | "add" => '+',
+            "minus" | "subtract" => '-',
+            "multiply" => '*',
+            "divide" => '/',
+            "decimal" => '.',
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c @ ('0'..='9' | '+' | '-' | '*' | '/' | '.')), None) => c,
+                    _ => return None,
+                }
+            }
+        };
+        return Some(KeyCode::Keypad(c));
     }
+
+    let code = match lower {
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" | "bs" => KeyCode::Backspace,
+        "escape" | "esc" => KeyCode::Escape,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" | "pgup" => KeyCode::PageUp,
+        "pagedown" | "pgdn" => KeyCode::PageDown,
+        "insert" | "ins" => KeyCode::Insert,
+        "delete" | "del" => KeyCode::Delete,
+        "space" => KeyCode::Char(' '),
+        _ => return None,
+    };
+    Some(code)
 }
 
-pub fn parse_mouse_action(action: &str) -> Result<MouseAction> {
+// Well inside the double-click time of common toolkits (250-500ms).
+pub const DEFAULT_CLICK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub fn parse_mouse_action(action: &str, button: MouseButton) -> Result<MouseAction> {
     match action.to_lowercase().as_str() {
-        "press" | "click" => Ok(MouseAction::Press),
-        "release" => Ok(MouseAction::Release),
+        "press" | "click" => Ok(MouseAction::Press(button)),
+        "release" => Ok(MouseAction::Release(button)),
         "move" => Ok(MouseAction::Move),
+        "drag" => Ok(MouseAction::Drag(button)),
         "scrollup" | "scroll-up" => Ok(MouseAction::ScrollUp),
         "scrolldown" | "scroll-down" => Ok(MouseAction::ScrollDown),
+        "scrollleft" | "scroll-left" => Ok(MouseAction::ScrollLeft),
+        "scrollright" | "scroll-right" => Ok(MouseAction::ScrollRight),
         _ => Err(Error::UnknownMouseAction(action.to_string())),
     }
 }
 
-pub fn mouse_sgr_sequence(action: &MouseAction, col: u16, row: u16) -> Vec<u8> {
-    let (button, suffix) = match action {
-        MouseAction::Press => (0, 'M'),
-        MouseAction::Release => (0, 'm'),
-        MouseAction::Move => (32, 'M'),
-        MouseAction::ScrollUp => (64, 'M'),
-        MouseAction::ScrollDown => (65, 'M'),
+pub fn focus_sequence(gained: bool) -> &'static [u8] {
+    if gained {
+        b"\x1b[I"
+    } else {
+        b"\x1b[O"
+    }
+}
+
+// Newlines are sent as carriage returns, as a terminal does for pasted text.
+// Any end-of-paste marker inside the text is dropped so it cannot terminate
+// the paste early.
+pub fn paste_sequence(text: &str, bracketed: bool) -> Vec<u8> {
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if !bracketed {
+        return text.into_bytes();
+    }
+    // Removing one marker can join its neighbours into another, so keep
+    // going until none is left.
+    let mut text = text;
+    while text.contains("\x1b[201~") {
+        text = text.replace("\x1b[201~", "");
+    }
+    let mut seq = b"\x1b[200~".to_vec();
+    seq.extend(text.into_bytes());
+    seq.extend(b"\x1b[201~");
+    seq
+}
+
+impl MouseEvent {
+    pub fn new(action: MouseAction, col: u16, row: u16) -> Self {
+        MouseEvent { action, col, row, modifiers: Modifiers::NONE }
+    }
+
+    // Whether an app that enabled this tracking mode is told about the event.
+    pub fn reported_in(&self, mode: MouseMode) -> bool {
+        match mode {
+            MouseMode::None => false,
+            MouseMode::Press => !matches!(self.action, MouseAction::Release(_) | MouseAction::Move | MouseAction::Drag(_)),
+            MouseMode::PressRelease => !matches!(self.action, MouseAction::Move | MouseAction::Drag(_)),
+            MouseMode::ButtonMotion => self.action != MouseAction::Move,
+            MouseMode::AnyMotion => true,
+        }
+    }
+
+    // Encodes the event the way the app asked for it, or not at all if its
+    // tracking mode does not cover it. Apps that never enabled mouse tracking
+    // get SGR so programs reading raw input can still be driven.
+    pub fn encode_for(&self, modes: &TerminalModes) -> Vec<u8> {
+        match modes.mouse_mode {
+            MouseMode::None => self.encode(MouseEncoding::Sgr),
+            mode if !self.reported_in(mode) => Vec::new(),
+            // X10 compatibility mode does not report modifiers.
+            MouseMode::Press => MouseEvent { modifiers: Modifiers::NONE, ..*self }.encode(modes.mouse_encoding),
+            _ => self.encode(modes.mouse_encoding),
+        }
+    }
+
+    pub fn encode(&self, encoding: MouseEncoding) -> Vec<u8> {
+        let sgr = encoding == MouseEncoding::Sgr;
+        let button = |b: MouseButton| match b {
+            MouseButton::Left => 0,
+            MouseButton::Middle => 1,
+            MouseButton::Right => 2,
+        };
+        let mut code: u32 = match self.action {
+            MouseAction::Press(b) => button(b),
+            // Only SGR can say which button was released.
+            MouseAction::Release(b) if sgr => button(b),
+            MouseAction::Release(_) => 3,
+            MouseAction::Move => 3 + 32,
+            MouseAction::Drag(b) => button(b) + 32,
+            MouseAction::ScrollUp => 64,
+            MouseAction::ScrollDown => 65,
+            MouseAction::ScrollLeft => 66,
+            MouseAction::ScrollRight => 67,
+        };
+        let mods = self.modifiers;
+        code += 4 * mods.shift as u32 + 8 * (mods.alt || mods.meta) as u32 + 16 * mods.ctrl as u32;
+        let (x, y) = (self.col as u32 + 1, self.row as u32 + 1);
+
+        match encoding {
+            MouseEncoding::Sgr => {
+                let suffix = if matches!(self.action, MouseAction::Release(_)) { 'm' } else { 'M' };
+                format!("\x1b[<{};{};{}{}", code, x, y, suffix).into_bytes()
+            }
+            MouseEncoding::Urxvt => format!("\x1b[{};{};{}M", code + 32, x, y).into_bytes(),
+            // Values are offset by 32 and sent as single bytes, so coordinates
+            // past 223 cannot be represented and are clamped.
+            MouseEncoding::Default => {
+                let byte = |v: u32| (v + 32).min(255) as u8;
+                vec![0x1b, b'[', b'M', byte(code), byte(x), byte(y)]
+            }
+            // Like the default encoding but each value is a UTF-8 character,
+            // extending the range to 2015.
+            MouseEncoding::Utf8 => {
+                let mut seq = b"\x1b[M".to_vec();
+                for v in [code, x, y] {
+                    let c = char::from_u32((v + 32).min(2047)).unwrap_or(' ');
+                    seq.extend(c.to_string().into_bytes());
+                }
+                seq
+            }
+        }
+    }
+}
+
+// Cells visited moving from one point to another in a straight line,
+// excluding the start and including the end.
+pub fn drag_path(from: (u16, u16), to: (u16, u16)) -> Vec<(u16, u16)> {
+    let (dx, dy) = (to.0 as i32 - from.0 as i32, to.1 as i32 - from.1 as i32);
+    let steps = dx.abs().max(dy.abs());
+    // Halfway points round towards the start.
+    let lerp = |start: u16, delta: i32, i: i32| {
+        let offset = (2 * delta.abs() * i + steps - 1) / (2 * steps);
+        (start as i32 + offset * delta.signum()) as u16
     };
-    format!("\x1b[<{};{};{}{}", button, col + 1, row + 1, suffix).into_bytes()
+    (1..=steps).map(|i| (lerp(from.0, dx, i), lerp(from.1, dy, i))).collect()
 }
 
 #[cfg(test)]
@@ -155,41 +781,41 @@ mod tests {
 
     #[test]
     fn test_parse_basic_keys() {
-        assert_eq!(parse_key_name("enter").unwrap(), Key::Enter);
-        assert_eq!(parse_key_name("Enter").unwrap(), Key::Enter);
-        assert_eq!(parse_key_name("return").unwrap(), Key::Enter);
-        assert_eq!(parse_key_name("tab").unwrap(), Key::Tab);
-        assert_eq!(parse_key_name("escape").unwrap(), Key::Escape);
-        assert_eq!(parse_key_name("esc").unwrap(), Key::Escape);
-        assert_eq!(parse_key_name("space").unwrap(), Key::Char(' '));
+        assert_eq!(parse_key_name("enter").unwrap(), Key::new(KeyCode::Enter));
+        assert_eq!(parse_key_name("Enter").unwrap(), Key::new(KeyCode::Enter));
+        assert_eq!(parse_key_name("return").unwrap(), Key::new(KeyCode::Enter));
+        assert_eq!(parse_key_name("tab").unwrap(), Key::new(KeyCode::Tab));
+        assert_eq!(parse_key_name("escape").unwrap(), Key::new(KeyCode::Escape));
+        assert_eq!(parse_key_name("esc").unwrap(), Key::new(KeyCode::Escape));
+        assert_eq!(parse_key_name("space").unwrap(), Key::new(KeyCode::Char(' ')));
     }
 
     #[test]
     fn test_parse_arrow_keys() {
-        assert_eq!(parse_key_name("up").unwrap(), Key::Up);
-        assert_eq!(parse_key_name("down").unwrap(), Key::Down);
-        assert_eq!(parse_key_name("left").unwrap(), Key::Left);
-        assert_eq!(parse_key_name("right").unwrap(), Key::Right);
+        assert_eq!(parse_key_name("up").unwrap(), Key::new(KeyCode::Up));
+        assert_eq!(parse_key_name("down").unwrap(), Key::new(KeyCode::Down));
+        assert_eq!(parse_key_name("left").unwrap(), Key::new(KeyCode::Left));
+        assert_eq!(parse_key_name("right").unwrap(), Key::new(KeyCode::Right));
     }
 
     #[test]
     fn test_parse_function_keys() {
-        assert_eq!(parse_key_name("f1").unwrap(), Key::F(1));
-        assert_eq!(parse_key_name("F5").unwrap(), Key::F(5));
-        assert_eq!(parse_key_name("f12").unwrap(), Key::F(12));
+        assert_eq!(parse_key_name("f1").unwrap(), Key::new(KeyCode::F(1)));
+        assert_eq!(parse_key_name("F5").unwrap(), Key::new(KeyCode::F(5)));
+        assert_eq!(parse_key_name("f12").unwrap(), Key::new(KeyCode::F(12)));
     }
 
     #[test]
     fn test_parse_ctrl_keys() {
-        assert_eq!(parse_key_name("ctrl+c").unwrap(), Key::Ctrl('c'));
-        assert_eq!(parse_key_name("ctrl-z").unwrap(), Key::Ctrl('z'));
-        assert_eq!(parse_key_name("Ctrl+A").unwrap(), Key::Ctrl('a'));
+        assert_eq!(parse_key_name("ctrl+c").unwrap(), Key::with(KeyCode::Char('c'), Modifiers::CTRL));
+        assert_eq!(parse_key_name("ctrl-z").unwrap(), Key::with(KeyCode::Char('z'), Modifiers::CTRL));
+        assert_eq!(parse_key_name("Ctrl+A").unwrap(), Key::with(KeyCode::Char('a'), Modifiers::CTRL));
     }
 
     #[test]
     fn test_parse_alt_keys() {
-        assert_eq!(parse_key_name("alt+x").unwrap(), Key::Alt('x'));
-        assert_eq!(parse_key_name("Alt-F").unwrap(), Key::Alt('f'));
+        assert_eq!(parse_key_name("alt+x").unwrap(), Key::with(KeyCode::Char('x'), Modifiers::ALT));
+        assert_eq!(parse_key_name("Alt-F").unwrap(), Key::with(KeyCode::Char('f'), Modifiers::ALT));
     }
 
     #[test]
@@ -199,33 +825,347 @@ mod tests {
 
     #[test]
     fn test_escape_sequences() {
-        assert_eq!(Key::Enter.to_escape_sequence(), vec![13]);
-        assert_eq!(Key::Tab.to_escape_sequence(), vec![9]);
-        assert_eq!(Key::Up.to_escape_sequence(), b"\x1b[A".to_vec());
-        assert_eq!(Key::Ctrl('c').to_escape_sequence(), vec![3]);
-        assert_eq!(Key::Ctrl('a').to_escape_sequence(), vec![1]);
-        assert_eq!(Key::F(1).to_escape_sequence(), b"\x1bOP".to_vec());
-        assert_eq!(Key::Char('a').to_escape_sequence(), b"a".to_vec());
+        assert_eq!(Key::new(KeyCode::Enter).to_escape_sequence(), vec![13]);
+        assert_eq!(Key::new(KeyCode::Tab).to_escape_sequence(), vec![9]);
+        assert_eq!(Key::new(KeyCode::Up).to_escape_sequence(), b"\x1b[A".to_vec());
+        assert_eq!(Key::with(KeyCode::Char('c'), Modifiers::CTRL).to_escape_sequence(), vec![3]);
+        assert_eq!(Key::with(KeyCode::Char('a'), Modifiers::CTRL).to_escape_sequence(), vec![1]);
+        assert_eq!(Key::new(KeyCode::F(1)).to_escape_sequence(), b"\x1bOP".to_vec());
+        assert_eq!(Key::new(KeyCode::Char('a')).to_escape_sequence(), b"a".to_vec());
+    }
+
+    #[test]
+    fn test_parse_keypad_keys() {
+        assert_eq!(parse_key_name("kp5").unwrap(), Key::new(KeyCode::Keypad('5')));
+        assert_eq!(parse_key_name("kp-enter").unwrap(), Key::new(KeyCode::Keypad('\r')));
+        assert_eq!(parse_key_name("KP_Plus").unwrap(), Key::new(KeyCode::Keypad('+')));
+        assert_eq!(parse_key_name("kp/").unwrap(), Key::new(KeyCode::Keypad('/')));
+        assert!(parse_key_name("kp").is_err());
+        assert!(parse_key_name("kp12").is_err());
+    }
+
+    #[test]
+    fn test_application_cursor_encoding() {
+        let app = KeyModes { cursor: KeyEncoding::Application, ..Default::default() };
+        assert_eq!(Key::new(KeyCode::Up).encode(app), b"\x1bOA".to_vec());
+        assert_eq!(Key::new(KeyCode::Left).encode(app), b"\x1bOD".to_vec());
+        assert_eq!(Key::new(KeyCode::Home).encode(app), b"\x1bOH".to_vec());
+        assert_eq!(Key::new(KeyCode::End).encode(app), b"\x1bOF".to_vec());
+        assert_eq!(Key::new(KeyCode::End).to_escape_sequence(), b"\x1b[F".to_vec());
+        assert_eq!(Key::new(KeyCode::PageUp).encode(app), b"\x1b[5~".to_vec());
+        assert_eq!(Key::new(KeyCode::Keypad('1')).encode(app), b"1".to_vec());
+    }
+
+    #[test]
+    fn test_application_keypad_encoding() {
+        let app = KeyModes { keypad: KeyEncoding::Application, ..Default::default() };
+        assert_eq!(Key::new(KeyCode::Keypad('0')).encode(app), b"\x1bOp".to_vec());
+        assert_eq!(Key::new(KeyCode::Keypad('9')).encode(app), b"\x1bOy".to_vec());
+        assert_eq!(Key::new(KeyCode::Keypad('\r')).encode(app), b"\x1bOM".to_vec());
+        assert_eq!(Key::new(KeyCode::Keypad('+')).encode(app), b"\x1bOk".to_vec());
+        assert_eq!(Key::new(KeyCode::Keypad('\r')).to_escape_sequence(), b"\r".to_vec());
+        assert_eq!(Key::new(KeyCode::Up).encode(app), b"\x1b[A".to_vec());
+    }
+
+    #[test]
+    fn test_key_override() {
+        let terminal = KeyModes { cursor: KeyEncoding::Application, keypad: KeyEncoding::Application, ..Default::default() };
+        let normal_cursor = KeyOverride { cursor: Some(KeyEncoding::Normal), ..Default::default() };
+        assert_eq!(normal_cursor.apply(terminal), KeyModes { cursor: KeyEncoding::Normal, ..terminal });
+        let legacy = KeyOverride { kitty_flags: Some(0), ..Default::default() };
+        assert_eq!(legacy.apply(KeyModes { kitty_flags: 3, ..terminal }), terminal);
+        assert_eq!(KeyOverride::default().apply(terminal), terminal);
+    }
+
+    #[test]
+    fn test_paste_sequence() {
+        assert_eq!(paste_sequence("a\nb\r\nc", false), b"a\rb\rc".to_vec());
+        assert_eq!(paste_sequence("if x:\n    y", true), b"\x1b[200~if x:\r    y\x1b[201~".to_vec());
+        assert_eq!(paste_sequence("a\x1b[201~b", true), b"\x1b[200~ab\x1b[201~".to_vec());
+        assert_eq!(paste_sequence("\x1b[20\x1b[201~1~", true), b"\x1b[200~\x1b[201~".to_vec());
     }
 
     #[test]
     fn test_mouse_sgr() {
-        let seq = mouse_sgr_sequence(&MouseAction::Press, 10, 5);
+        let seq = MouseEvent::new(MouseAction::Press(MouseButton::Left), 10, 5).encode(MouseEncoding::Sgr);
         assert_eq!(seq, b"\x1b[<0;11;6M".to_vec());
 
-        let seq = mouse_sgr_sequence(&MouseAction::Release, 10, 5);
+        let seq = MouseEvent::new(MouseAction::Release(MouseButton::Left), 10, 5).encode(MouseEncoding::Sgr);
         assert_eq!(seq, b"\x1b[<0;11;6m".to_vec());
 
-        let seq = mouse_sgr_sequence(&MouseAction::ScrollUp, 0, 0);
+        let seq = MouseEvent::new(MouseAction::ScrollUp, 0, 0).encode(MouseEncoding::Sgr);
         assert_eq!(seq, b"\x1b[<64;1;1M".to_vec());
     }
 
+    #[test]
+    fn test_mouse_buttons_and_modifiers() {
+        let sgr = |action, modifiers| MouseEvent { action, col: 0, row: 0, modifiers }.encode(MouseEncoding::Sgr);
+        assert_eq!(sgr(MouseAction::Press(MouseButton::Right), Modifiers::NONE), b"\x1b[<2;1;1M".to_vec());
+        assert_eq!(sgr(MouseAction::Release(MouseButton::Middle), Modifiers::NONE), b"\x1b[<1;1;1m".to_vec());
+        assert_eq!(sgr(MouseAction::Drag(MouseButton::Left), Modifiers::NONE), b"\x1b[<32;1;1M".to_vec());
+        assert_eq!(sgr(MouseAction::Move, Modifiers::NONE), b"\x1b[<35;1;1M".to_vec());
+        assert_eq!(sgr(MouseAction::ScrollRight, Modifiers::NONE), b"\x1b[<67;1;1M".to_vec());
+        assert_eq!(sgr(MouseAction::Press(MouseButton::Left), Modifiers::SHIFT), b"\x1b[<4;1;1M".to_vec());
+        let ctrl_alt = Modifiers { ctrl: true, alt: true, ..Modifiers::NONE };
+        assert_eq!(sgr(MouseAction::ScrollDown, ctrl_alt), b"\x1b[<89;1;1M".to_vec());
+    }
+
+    #[test]
+    fn test_legacy_mouse_encodings() {
+        let press = MouseEvent::new(MouseAction::Press(MouseButton::Left), 10, 5);
+        let release = MouseEvent::new(MouseAction::Release(MouseButton::Right), 10, 5);
+        assert_eq!(press.encode(MouseEncoding::Default), b"\x1b[M +&".to_vec());
+        assert_eq!(release.encode(MouseEncoding::Default), b"\x1b[M#+&".to_vec());
+        assert_eq!(press.encode(MouseEncoding::Urxvt), b"\x1b[32;11;6M".to_vec());
+        assert_eq!(release.encode(MouseEncoding::Urxvt), b"\x1b[35;11;6M".to_vec());
+
+        let far = MouseEvent::new(MouseAction::Press(MouseButton::Left), 300, 0);
+        assert_eq!(far.encode(MouseEncoding::Default), vec![0x1b, b'[', b'M', 32, 255, 33]);
+        let mut utf8 = b"\x1b[M ".to_vec();
+        utf8.extend("\u{14d}!".as_bytes());
+        assert_eq!(far.encode(MouseEncoding::Utf8), utf8);
+    }
+
+    #[test]
+    fn test_mouse_tracking_modes() {
+        let modes = |mouse_mode, mouse_encoding| TerminalModes { mouse_mode, mouse_encoding, ..Default::default() };
+        let press = MouseEvent { modifiers: Modifiers::CTRL, ..MouseEvent::new(MouseAction::Press(MouseButton::Left), 0, 0) };
+        let release = MouseEvent::new(MouseAction::Release(MouseButton::Left), 0, 0);
+        let drag = MouseEvent::new(MouseAction::Drag(MouseButton::Left), 1, 0);
+        let motion = MouseEvent::new(MouseAction::Move, 1, 0);
+
+        let x10 = modes(MouseMode::Press, MouseEncoding::Default);
+        assert_eq!(press.encode_for(&x10), b"\x1b[M !!".to_vec());
+        assert!(release.encode_for(&x10).is_empty());
+
+        let normal = modes(MouseMode::PressRelease, MouseEncoding::Sgr);
+        assert_eq!(press.encode_for(&normal), b"\x1b[<16;1;1M".to_vec());
+        assert!(!release.encode_for(&normal).is_empty());
+        assert!(drag.encode_for(&normal).is_empty());
+
+        let button = modes(MouseMode::ButtonMotion, MouseEncoding::Sgr);
+        assert!(!drag.encode_for(&button).is_empty());
+        assert!(motion.encode_for(&button).is_empty());
+        assert!(!motion.encode_for(&modes(MouseMode::AnyMotion, MouseEncoding::Sgr)).is_empty());
+
+        assert_eq!(release.encode_for(&TerminalModes::default()), b"\x1b[<0;1;1m".to_vec());
+    }
+
+    #[test]
+    fn test_parse_key_sequence() {
+        let tokens = parse_key_sequence("esc :wq  ctrl+x\tenter").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                KeyToken::Key(Key::new(KeyCode::Escape)),
+                KeyToken::Text(":wq".to_string()),
+                KeyToken::Key(Key::with(KeyCode::Char('x'), Modifiers::CTRL)),
+                KeyToken::Key(Key::new(KeyCode::Enter)),
+            ]
+        );
+        assert!(parse_key_sequence("  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_key_sequence_literals() {
+        let tokens = parse_key_sequence(r#"i "hello  world" 'it\'s' "a\"b" esc"#).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                KeyToken::Key(Key::new(KeyCode::Char('i'))),
+                KeyToken::Text("hello  world".to_string()),
+                KeyToken::Text("it's".to_string()),
+                KeyToken::Text("a\"b".to_string()),
+                KeyToken::Key(Key::new(KeyCode::Escape)),
+            ]
+        );
+        let quoted = quote_key_text(r#"say "hi" \ bye"#);
+        assert_eq!(parse_key_sequence(&quoted).unwrap(), vec![KeyToken::Text(r#"say "hi" \ bye"#.to_string())]);
+        assert!(matches!(parse_key_sequence("\"open"), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_parse_key_sequence_rejects_typos() {
+        for typo in ["entr", "ctrl+sx", "ctlr+x", "alt-foo", "f25"] {
+            assert!(matches!(parse_key_sequence(&format!("esc {}", typo)), Err(Error::UnknownKey(_))), "{}", typo);
+        }
+    }
+
+    #[test]
+    fn test_drag_path() {
+        assert_eq!(drag_path((0, 0), (3, 0)), vec![(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(drag_path((4, 4), (2, 0)), vec![(4, 3), (3, 2), (3, 1), (2, 0)]);
+        assert!(drag_path((1, 1), (1, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_parse_modifier_combinations() {
+        let ctrl_shift = Modifiers { ctrl: true, shift: true, ..Modifiers::NONE };
+        assert_eq!(parse_key_name("shift+tab").unwrap(), Key::with(KeyCode::Tab, Modifiers::SHIFT));
+        assert_eq!(parse_key_name("ctrl+up").unwrap(), Key::with(KeyCode::Up, Modifiers::CTRL));
+        assert_eq!(parse_key_name("Ctrl+Shift+Left").unwrap(), Key::with(KeyCode::Left, ctrl_shift));
+        assert_eq!(parse_key_name("alt-enter").unwrap(), Key::with(KeyCode::Enter, Modifiers::ALT));
+        assert_eq!(parse_key_name("ctrl+space").unwrap(), Key::with(KeyCode::Char(' '), Modifiers::CTRL));
+        assert_eq!(parse_key_name("ctrl+]").unwrap(), Key::with(KeyCode::Char(']'), Modifiers::CTRL));
+        assert_eq!(parse_key_name("ctrl++").unwrap(), Key::with(KeyCode::Char('+'), Modifiers::CTRL));
+        assert_eq!(parse_key_name("ctrl+-").unwrap(), Key::with(KeyCode::Char('-'), Modifiers::CTRL));
+        assert_eq!(parse_key_name("F13").unwrap(), Key::new(KeyCode::F(13)));
+        assert_eq!(parse_key_name("shift+f24").unwrap(), Key::with(KeyCode::F(24), Modifiers::SHIFT));
+        assert_eq!(parse_key_name("Q").unwrap(), Key::new(KeyCode::Char('Q')));
+        assert!(parse_key_name("f25").is_err());
+        assert!(parse_key_name("ctrl+").is_err());
+        assert!(parse_key_name("hyper+a").is_err());
+    }
+
+    #[test]
+    fn test_modified_key_sequences() {
+        let seq = |name: &str| parse_key_name(name).unwrap().to_escape_sequence();
+        assert_eq!(seq("ctrl+up"), b"\x1b[1;5A".to_vec());
+        assert_eq!(seq("shift+right"), b"\x1b[1;2C".to_vec());
+        assert_eq!(seq("ctrl+shift+left"), b"\x1b[1;6D".to_vec());
+        assert_eq!(seq("alt+end"), b"\x1b[1;3F".to_vec());
+        assert_eq!(seq("shift+tab"), b"\x1b[Z".to_vec());
+        assert_eq!(seq("ctrl+shift+tab"), b"\x1b[1;6Z".to_vec());
+        assert_eq!(seq("alt+shift+tab"), b"\x1b[1;4Z".to_vec());
+        assert_eq!(seq("alt+enter"), b"\x1b\r".to_vec());
+        assert_eq!(seq("ctrl+delete"), b"\x1b[3;5~".to_vec());
+        assert_eq!(seq("shift+f1"), b"\x1b[1;2P".to_vec());
+        assert_eq!(seq("ctrl+f5"), b"\x1b[15;5~".to_vec());
+        assert_eq!(seq("meta+up"), b"\x1b[1;9A".to_vec());
+    }
+
+    #[test]
+    fn test_modified_char_sequences() {
+        let seq = |name: &str| parse_key_name(name).unwrap().to_escape_sequence();
+        assert_eq!(seq("ctrl+space"), vec![0x00]);
+        assert_eq!(seq("ctrl+]"), vec![0x1d]);
+        assert_eq!(seq("ctrl+["), vec![0x1b]);
+        assert_eq!(seq("ctrl+/"), vec![0x1f]);
+        assert_eq!(seq("ctrl+shift+a"), vec![0x01]);
+        assert_eq!(seq("shift+a"), b"A".to_vec());
+        assert_eq!(seq("alt+shift+b"), b"\x1bB".to_vec());
+        assert_eq!(seq("ctrl+alt+c"), vec![0x1b, 0x03]);
+        assert_eq!(seq("ctrl+backspace"), vec![0x08]);
+        assert_eq!(seq("alt+backspace"), vec![0x1b, 0x7f]);
+    }
+
+    #[test]
+    fn test_ctrl_without_control_byte_is_rejected() {
+        let encode = |name: &str, modes| parse_key_name(name).unwrap().try_encode(KeyEvent::Press, modes);
+        let legacy = KeyModes::default();
+        assert!(matches!(encode("ctrl+1", legacy), Err(Error::UnknownKey(name)) if name == "ctrl+1"));
+        assert!(matches!(encode("ctrl+enter", legacy), Err(Error::UnknownKey(name)) if name == "ctrl+enter"));
+        assert!(encode("ctrl+tab", legacy).is_err());
+        assert_eq!(encode("ctrl+2", legacy).unwrap(), vec![0x00]);
+        assert_eq!(encode("ctrl+shift+tab", legacy).unwrap(), b"\x1b[1;6Z".to_vec());
+        assert_eq!(encode("ctrl+up", legacy).unwrap(), b"\x1b[1;5A".to_vec());
+
+        let other_keys = KeyModes { modify_other_keys: 1, ..Default::default() };
+        assert_eq!(encode("ctrl+1", other_keys).unwrap(), b"\x1b[27;5;49~".to_vec());
+        assert_eq!(encode("ctrl+enter", kitty(KITTY_DISAMBIGUATE)).unwrap(), b"\x1b[13;5u".to_vec());
+    }
+
+    #[test]
+    fn test_high_function_keys() {
+        let seq = |name: &str| parse_key_name(name).unwrap().to_escape_sequence();
+        assert_eq!(seq("f13"), b"\x1b[1;2P".to_vec());
+        assert_eq!(seq("f16"), b"\x1b[1;2S".to_vec());
+        assert_eq!(seq("f17"), b"\x1b[15;2~".to_vec());
+        assert_eq!(seq("f24"), b"\x1b[24;2~".to_vec());
+        assert_eq!(seq("ctrl+f13"), b"\x1b[1;6P".to_vec());
+    }
+
+    #[test]
+    fn test_modified_keys_ignore_application_cursor() {
+        let app = KeyModes { cursor: KeyEncoding::Application, ..Default::default() };
+        assert_eq!(Key::with(KeyCode::Up, Modifiers::CTRL).encode(app), b"\x1b[1;5A".to_vec());
+    }
+
+    fn kitty(flags: u8) -> KeyModes {
+        KeyModes { kitty_flags: flags, ..Default::default() }
+    }
+
+    #[test]
+    fn test_kitty_disambiguate() {
+        let seq = |name: &str| parse_key_name(name).unwrap().encode(kitty(KITTY_DISAMBIGUATE));
+        assert_eq!(seq("tab"), b"\t".to_vec());
+        assert_eq!(seq("ctrl+i"), b"\x1b[105;5u".to_vec());
+        assert_eq!(seq("escape"), b"\x1b[27u".to_vec());
+        assert_eq!(seq("alt+a"), b"\x1b[97;3u".to_vec());
+        assert_eq!(seq("shift+a"), b"A".to_vec());
+        assert_eq!(seq("ctrl+enter"), b"\x1b[13;5u".to_vec());
+        assert_eq!(seq("shift+tab"), b"\x1b[9;2u".to_vec());
+        assert_eq!(seq("enter"), b"\r".to_vec());
+        assert_eq!(seq("up"), b"\x1b[A".to_vec());
+        assert_eq!(seq("ctrl+up"), b"\x1b[1;5A".to_vec());
+        assert_eq!(seq("f1"), b"\x1b[P".to_vec());
+        assert_eq!(seq("f3"), b"\x1b[13~".to_vec());
+        assert_eq!(seq("f13"), b"\x1b[57376u".to_vec());
+        assert_eq!(seq("kp1"), b"1".to_vec());
+        assert_eq!(seq("kp+"), b"+".to_vec());
+        assert_eq!(seq("ctrl+kp1"), b"\x1b[57400;5u".to_vec());
+        assert_eq!(seq("kp-enter"), b"\x1b[57414u".to_vec());
+    }
+
+    #[test]
+    fn test_kitty_events() {
+        let modes = kitty(KITTY_DISAMBIGUATE | KITTY_REPORT_EVENTS);
+        let ctrl_a = parse_key_name("ctrl+a").unwrap();
+        assert_eq!(ctrl_a.encode_event(KeyEvent::Press, modes), b"\x1b[97;5u".to_vec());
+        assert_eq!(ctrl_a.encode_event(KeyEvent::Repeat, modes), b"\x1b[97;5:2u".to_vec());
+        assert_eq!(ctrl_a.encode_event(KeyEvent::Release, modes), b"\x1b[97;5:3u".to_vec());
+        let up = Key::new(KeyCode::Up);
+        assert_eq!(up.encode_event(KeyEvent::Release, modes), b"\x1b[1;1:3A".to_vec());
+        // Text keys stay plain text, which has no release form.
+        let a = Key::new(KeyCode::Char('a'));
+        assert_eq!(a.encode_event(KeyEvent::Press, modes), b"a".to_vec());
+        assert!(a.encode_event(KeyEvent::Release, modes).is_empty());
+        // Without event reporting releases are dropped entirely.
+        assert!(ctrl_a.encode_event(KeyEvent::Release, kitty(KITTY_DISAMBIGUATE)).is_empty());
+        assert!(ctrl_a.encode_event(KeyEvent::Release, KeyModes::default()).is_empty());
+    }
+
+    #[test]
+    fn test_kitty_report_all_keys() {
+        let modes = kitty(KITTY_DISAMBIGUATE | KITTY_REPORT_EVENTS | KITTY_REPORT_ALL_KEYS);
+        let a = Key::new(KeyCode::Char('a'));
+        assert_eq!(a.encode(modes), b"\x1b[97u".to_vec());
+        assert_eq!(a.encode_event(KeyEvent::Release, modes), b"\x1b[97;1:3u".to_vec());
+        assert_eq!(Key::new(KeyCode::Enter).encode(modes), b"\x1b[13u".to_vec());
+        assert_eq!(parse_key_name("shift+a").unwrap().encode(modes), b"\x1b[97;2u".to_vec());
+
+        let modes = kitty(KITTY_DISAMBIGUATE | KITTY_REPORT_ALTERNATES | KITTY_REPORT_ALL_KEYS | KITTY_REPORT_TEXT);
+        assert_eq!(parse_key_name("shift+a").unwrap().encode(modes), b"\x1b[97:65;2;65u".to_vec());
+        assert_eq!(a.encode(modes), b"\x1b[97;;97u".to_vec());
+        assert_eq!(parse_key_name("ctrl+a").unwrap().encode(modes), b"\x1b[97;5u".to_vec());
+        assert_eq!(parse_key_name("kp1").unwrap().encode(modes), b"\x1b[57400;;49u".to_vec());
+    }
+
+    #[test]
+    fn test_modify_other_keys() {
+        let level = |n: u8| KeyModes { modify_other_keys: n, ..Default::default() };
+        let seq = |name: &str, n: u8| parse_key_name(name).unwrap().encode(level(n));
+        assert_eq!(seq("ctrl+a", 1), vec![0x01]);
+        assert_eq!(seq("ctrl+shift+a", 1), b"\x1b[27;6;65~".to_vec());
+        assert_eq!(seq("ctrl+enter", 1), b"\x1b[27;5;13~".to_vec());
+        assert_eq!(seq("ctrl+1", 1), b"\x1b[27;5;49~".to_vec());
+        assert_eq!(seq("alt+a", 1), b"\x1ba".to_vec());
+        assert_eq!(seq("ctrl+a", 2), b"\x1b[27;5;97~".to_vec());
+        assert_eq!(seq("alt+a", 2), b"\x1b[27;3;97~".to_vec());
+        assert_eq!(seq("shift+a", 2), b"A".to_vec());
+        assert_eq!(seq("ctrl+up", 2), b"\x1b[1;5A".to_vec());
+        assert_eq!(seq("ctrl+i", 0), vec![0x09]);
+    }
+
     #[test]
     fn test_parse_mouse_action() {
-        assert_eq!(parse_mouse_action("press").unwrap(), MouseAction::Press);
-        assert_eq!(parse_mouse_action("click").unwrap(), MouseAction::Press);
-        assert_eq!(parse_mouse_action("release").unwrap(), MouseAction::Release);
-        assert_eq!(parse_mouse_action("scrollup").unwrap(), MouseAction::ScrollUp);
-        assert!(parse_mouse_action("invalid").is_err());
+        let left = MouseButton::Left;
+        assert_eq!(parse_mouse_action("press", left).unwrap(), MouseAction::Press(left));
+        assert_eq!(parse_mouse_action("click", left).unwrap(), MouseAction::Press(left));
+        assert_eq!(parse_mouse_action("release", left).unwrap(), MouseAction::Release(left));
+        assert_eq!(parse_mouse_action("scrollup", left).unwrap(), MouseAction::ScrollUp);
+        assert_eq!(parse_mouse_action("scroll-left", left).unwrap(), MouseAction::ScrollLeft);
+        assert_eq!(parse_mouse_action("drag", MouseButton::Right).unwrap(), MouseAction::Drag(MouseButton::Right));
+        assert!(parse_mouse_action("invalid", left).is_err());
     }
 }
@@ -1,9 +1,14 @@
 pub mod client;
+pub mod condition;
 pub mod diff;
 pub mod error;
 pub mod input;
+pub mod process;
 pub mod protocol;
 pub mod screen;
 pub mod server;
 pub mod session;
+pub mod style;
+pub mod terminal;
 pub mod trace;
+pub mod wait;
@@ -1,8 +1,13 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 use tui_wright::client;
+use tui_wright::condition::Condition;
+use tui_wright::input::{self, KeyEncoding, KeyEvent, KeyOverride, Modifiers, MouseButton};
 use tui_wright::protocol::{Request, Response};
+use tui_wright::screen::{ColorCompare, ColorInfo, Region, Theme};
 use tui_wright::server;
+use tui_wright::session::SpawnOptions;
+use tui_wright::style::{ColorQuery, StyleQuery};
 
 #[derive(Parser)]
 #[command(name = "tui-wright", about = "Playwright for Terminal UIs")]
@@ -26,6 +31,8 @@ enum Commands {
         /// Terminal rows
         #[arg(long, default_value = "24")]
         rows: u16,
+        #[command(flatten)]
+        terminal: TerminalArgs,
     },
     /// Get the current screen contents
     Screen {
@@ -34,6 +41,11 @@ enum Commands {
         /// Output as JSON with cell-level attributes
         #[arg(long)]
         json: bool,
+        /// Print the scrollback history instead of the visible screen
+        #[arg(long)]
+        scrollback: bool,
+        #[command(flatten)]
+        stable: StableArgs,
     },
     /// Type text into the session
     Type {
@@ -41,24 +53,141 @@ enum Commands {
         session: String,
         /// Text to type
         text: String,
+        /// Send one character at a time, this many milliseconds apart
+        #[arg(long, default_value = "0")]
+        delay_ms: u64,
+        /// Add a random extra delay of up to this many milliseconds between characters
+        #[arg(long, default_value = "0")]
+        jitter_ms: u64,
+    },
+    /// Paste text, bracketed if the app has enabled bracketed paste
+    Paste {
+        /// Session ID
+        session: String,
+        /// Text to paste, or - to read it from stdin
+        text: String,
+        /// Bracket the text even if the app has not enabled bracketed paste
+        #[arg(long)]
+        force: bool,
     },
     /// Send a special key
     Key {
         /// Session ID
         session: String,
-        /// Key name (enter, tab, ctrl+c, up, f5, etc.)
+        /// Key name with optional modifiers (enter, ctrl+c, shift+tab, ctrl+shift+left, f13, kp5, etc.)
         name: String,
+        /// Encode cursor keys this way regardless of the app's mode
+        #[arg(long, value_enum)]
+        cursor_keys: Option<KeyMode>,
+        /// Encode keypad keys this way regardless of the app's mode
+        #[arg(long, value_enum)]
+        keypad: Option<KeyMode>,
+        /// Key event to send; releases are only sent if the app asked for them
+        #[arg(long, value_enum, default_value = "press")]
+        event: EventKind,
+        /// Use these kitty keyboard flags instead of the ones the app pushed (0 for legacy)
+        #[arg(long)]
+        kitty_flags: Option<u8>,
+        /// Use this xterm modifyOtherKeys level instead of the app's
+        #[arg(long)]
+        modify_other_keys: Option<u8>,
+    },
+    /// Send a sequence of keys in one go, e.g. `keys <session> ctrl+x ctrl+s` or `keys <session> esc :wq enter`
+    Keys {
+        /// Session ID
+        session: String,
+        /// Key names, or text to type in quotes (an argument containing spaces is typed as text)
+        #[arg(required = true)]
+        keys: Vec<String>,
+        /// Milliseconds to wait between keys
+        #[arg(long, default_value = "0")]
+        delay_ms: u64,
     },
     /// Send a mouse event
     Mouse {
         /// Session ID
         session: String,
-        /// Mouse action (press, release, move, scrollup, scrolldown)
+        /// Mouse action (press, release, move, drag, scrollup, scrolldown, scrollleft, scrollright)
         action: String,
         /// Column (0-indexed)
         col: u16,
         /// Row (0-indexed)
         row: u16,
+        /// Button to press, release or drag with
+        #[arg(long, value_enum, default_value = "left")]
+        button: ButtonName,
+        #[command(flatten)]
+        modifiers: ModifierArgs,
+        /// Send this many clicks (press and release) instead of a single press, e.g. 2 for a double-click
+        #[arg(long)]
+        clicks: Option<u32>,
+        /// Milliseconds between clicks
+        #[arg(long, requires = "clicks")]
+        interval_ms: Option<u64>,
+    },
+    /// Drag the mouse from one cell to another, pressing and releasing a button
+    Drag {
+        /// Session ID
+        session: String,
+        /// Starting column (0-indexed)
+        from_col: u16,
+        /// Starting row (0-indexed)
+        from_row: u16,
+        /// Ending column (0-indexed)
+        to_col: u16,
+        /// Ending row (0-indexed)
+        to_row: u16,
+        /// Button to drag with
+        #[arg(long, value_enum, default_value = "left")]
+        button: ButtonName,
+        #[command(flatten)]
+        modifiers: ModifierArgs,
+    },
+    /// Report that the terminal gained or lost focus (ignored unless the app enabled focus events)
+    Focus {
+        /// Session ID
+        session: String,
+        #[arg(value_enum, default_value = "in")]
+        focus: FocusChange,
+        /// Report the change even if the app has not enabled focus events
+        #[arg(long)]
+        force: bool,
+    },
+    /// Find text on screen and print where it is (exit 1 if not found)
+    Find {
+        /// Session ID
+        session: String,
+        /// Text to find
+        pattern: String,
+        /// Treat the pattern as a regular expression
+        #[arg(long)]
+        regex: bool,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Find runs of cells with a given style (exit 1 if none)
+    FindStyle {
+        /// Session ID
+        session: String,
+        #[command(flatten)]
+        style: StyleArgs,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Click the centre of some text on screen
+    ClickText {
+        /// Session ID
+        session: String,
+        /// Text to click
+        pattern: String,
+        /// Treat the pattern as a regular expression
+        #[arg(long)]
+        regex: bool,
+        /// Which match to click when there are several (0-indexed)
+        #[arg(long, default_value = "0")]
+        index: usize,
     },
     /// Resize the terminal viewport
     Resize {
@@ -74,10 +203,49 @@ enum Commands {
         /// Session ID
         session: String,
     },
-    /// Kill a session
+    /// Kill a session and every process started in it
     Kill {
         /// Session ID
         session: String,
+        /// Milliseconds to wait after SIGTERM before sending SIGKILL
+        #[arg(long)]
+        grace_ms: Option<u64>,
+    },
+    /// Send a signal to the foreground process group
+    Signal {
+        /// Session ID
+        session: String,
+        /// Signal name or number (int, SIGTERM, tstp, cont, hup, winch, usr1, ...)
+        name: String,
+    },
+    /// Show whether the child is still running and how it exited
+    Status {
+        /// Session ID
+        session: String,
+    },
+    /// Wait for the child to exit, then exit with its exit code
+    WaitExit {
+        /// Session ID
+        session: String,
+        /// Timeout in milliseconds
+        #[arg(long, default_value = "5000")]
+        timeout: u64,
+    },
+    /// Show terminal modes set by the app (alternate screen, mouse reporting, ...)
+    Modes {
+        /// Session ID
+        session: String,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the foreground process and terminal mode (canonical/raw, echo)
+    ProcessInfo {
+        /// Session ID
+        session: String,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
     },
     /// List active sessions
     List,
@@ -90,13 +258,37 @@ enum Commands {
         /// Timeout in milliseconds
         #[arg(long, default_value = "5000")]
         timeout: u64,
+        #[command(flatten)]
+        condition: ConditionArgs,
+    },
+    /// Wait until no output has arrived for a quiet period (or timeout)
+    WaitStable {
+        /// Session ID
+        session: String,
+        /// Quiet period in milliseconds
+        #[arg(long, default_value = "200")]
+        quiet_ms: u64,
+        /// Timeout in milliseconds
+        #[arg(long, default_value = "5000")]
+        timeout: u64,
     },
     /// Assert that text is currently visible on screen
     Assert {
         /// Session ID
         session: String,
         /// Text to search for
-        text: String,
+        #[arg(required_unless_present = "snapshot")]
+        text: Option<String>,
+        #[command(flatten)]
+        condition: ConditionArgs,
+        #[command(flatten)]
+        stable: StableArgs,
+        /// Also require the screen to match this saved snapshot
+        #[arg(long, value_name = "FILE")]
+        snapshot: Option<String>,
+        /// How to compare cell colours against the snapshot
+        #[arg(long, value_enum, default_value = "resolved", requires = "snapshot")]
+        colors: ColorMode,
     },
     /// Spawn a session and run a command (spawn + type + enter)
     Run {
@@ -108,6 +300,8 @@ enum Commands {
         /// Terminal rows
         #[arg(long, default_value = "24")]
         rows: u16,
+        #[command(flatten)]
+        terminal: TerminalArgs,
     },
     /// Trace recording commands (asciicast v2 format)
     Trace {
@@ -121,6 +315,250 @@ enum Commands {
     },
 }
 
+#[derive(Args)]
+struct TerminalArgs {
+    /// Terminal name reported in reply to XTVERSION queries
+    #[arg(long)]
+    terminal_version: Option<String>,
+    /// Number of lines kept in the scrollback history
+    #[arg(long, default_value = "1000")]
+    scrollback: usize,
+    /// Milliseconds the session stays up after the command exits, so its final screen can still be read
+    #[arg(long, default_value = "60000")]
+    linger: u64,
+    /// Colour theme reported to OSC colour queries
+    #[arg(long, value_enum, default_value = "dark")]
+    theme: ThemeName,
+    /// Default foreground colour (#rrggbb), overriding the theme
+    #[arg(long, value_parser = parse_color)]
+    fg: Option<ColorInfo>,
+    /// Default background colour (#rrggbb), overriding the theme
+    #[arg(long, value_parser = parse_color)]
+    bg: Option<ColorInfo>,
+    /// Cursor colour (#rrggbb), overriding the theme
+    #[arg(long, value_parser = parse_color)]
+    cursor: Option<ColorInfo>,
+    /// Set an environment variable for the child (repeatable)
+    #[arg(long, value_name = "KEY=VAL", value_parser = parse_env)]
+    env: Vec<(String, String)>,
+    /// Start the child with an empty environment (PATH is kept)
+    #[arg(long)]
+    env_clear: bool,
+    /// TERM for the child
+    #[arg(long)]
+    term: Option<String>,
+    /// COLORTERM for the child (e.g. truecolor)
+    #[arg(long)]
+    colorterm: Option<String>,
+    /// Locale for the child, sets LANG and LC_ALL
+    #[arg(long)]
+    lang: Option<String>,
+    /// Reproducible environment: cleared, with fixed TERM, LANG and TZ and a temporary HOME
+    #[arg(long)]
+    hermetic: bool,
+    /// Milliseconds processes get to exit on kill before SIGKILL
+    #[arg(long, default_value = "2000")]
+    kill_grace_ms: u64,
+}
+
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid environment variable {:?}, expected KEY=VAL", s)),
+    }
+}
+
+#[derive(Args)]
+struct ConditionArgs {
+    /// Treat the text as a regular expression
+    #[arg(long)]
+    regex: bool,
+    /// Require the text to be absent instead
+    #[arg(long = "not")]
+    negate: bool,
+    /// Require exactly this many matches
+    #[arg(long)]
+    count: Option<usize>,
+    /// Only search these rows (START:END, 0-indexed, inclusive)
+    #[arg(long, value_parser = parse_rows, conflicts_with = "region")]
+    rows: Option<Region>,
+    /// Only search this rectangle (TOP,LEFT,BOTTOM,RIGHT, 0-indexed, inclusive)
+    #[arg(long, value_parser = parse_region)]
+    region: Option<Region>,
+    /// Also search the scrollback history
+    #[arg(long, conflicts_with_all = ["rows", "region"])]
+    scrollback: bool,
+    /// Print the matches and capture groups as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+impl ConditionArgs {
+    fn condition(&self, text: String) -> Condition {
+        Condition {
+            text,
+            regex: self.regex,
+            negate: self.negate,
+            count: self.count,
+            region: self.rows.or(self.region),
+            scrollback: self.scrollback,
+        }
+    }
+}
+
+fn parse_rows(s: &str) -> Result<Region, String> {
+    let (top, bottom) = s.split_once(':').unwrap_or((s, s));
+    match (top.parse(), bottom.parse()) {
+        (Ok(top), Ok(bottom)) => Ok(Region::rows(top, bottom)),
+        _ => Err(format!("invalid row range {:?}, expected START:END", s)),
+    }
+}
+
+fn parse_region(s: &str) -> Result<Region, String> {
+    let invalid = || format!("invalid region {:?}, expected TOP,LEFT,BOTTOM,RIGHT", s);
+    let bounds = s
+        .split(',')
+        .map(|n| n.trim().parse())
+        .collect::<Result<Vec<u16>, _>>()
+        .map_err(|_| invalid())?;
+    match bounds[..] {
+        [top, left, bottom, right] => Ok(Region { top, left, bottom, right }),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Args)]
+struct StyleArgs {
+    /// Foreground colour: default, a palette index or #rrggbb
+    #[arg(long, value_parser = parse_color_spec)]
+    fg: Option<ColorQuery>,
+    /// Background colour: default, a palette index or #rrggbb
+    #[arg(long, value_parser = parse_color_spec)]
+    bg: Option<ColorQuery>,
+    /// Only match bold cells
+    #[arg(long)]
+    bold: bool,
+    /// Only match dim cells
+    #[arg(long)]
+    dim: bool,
+    /// Only match italic cells
+    #[arg(long)]
+    italic: bool,
+    /// Only match underlined cells
+    #[arg(long)]
+    underline: bool,
+    /// Only match inverse-video cells
+    #[arg(long)]
+    inverse: bool,
+    /// Only match blinking cells
+    #[arg(long)]
+    blink: bool,
+    /// Only match struck-through cells
+    #[arg(long)]
+    strikethrough: bool,
+    /// Only search these rows (START:END, 0-indexed, inclusive)
+    #[arg(long, value_parser = parse_rows, conflicts_with = "region")]
+    rows: Option<Region>,
+    /// Only search this rectangle (TOP,LEFT,BOTTOM,RIGHT, 0-indexed, inclusive)
+    #[arg(long, value_parser = parse_region)]
+    region: Option<Region>,
+}
+
+impl StyleArgs {
+    fn query(self) -> StyleQuery {
+        StyleQuery {
+            fg: self.fg,
+            bg: self.bg,
+            bold: self.bold.then_some(true),
+            dim: self.dim.then_some(true),
+            italic: self.italic.then_some(true),
+            underline: self.underline.then_some(true),
+            inverse: self.inverse.then_some(true),
+            blink: self.blink.then_some(true),
+            strikethrough: self.strikethrough.then_some(true),
+            region: self.rows.or(self.region),
+        }
+    }
+}
+
+fn parse_color_spec(s: &str) -> Result<ColorQuery, String> {
+    ColorQuery::parse(s).ok_or_else(|| format!("invalid colour {:?}, expected default, 0-255 or #rrggbb", s))
+}
+
+#[derive(Args)]
+struct StableArgs {
+    /// First wait until no output has arrived for this many milliseconds
+    #[arg(long, value_name = "MS")]
+    wait_stable: Option<u64>,
+    /// Timeout in milliseconds for --wait-stable
+    #[arg(long, default_value = "5000")]
+    stable_timeout: u64,
+}
+
+impl StableArgs {
+    fn wait(&self, session: &str) {
+        let Some(quiet_ms) = self.wait_stable else {
+            return;
+        };
+        let request = Request::WaitStable { quiet_ms, timeout: self.stable_timeout };
+        match client::send_request(session, &request) {
+            Ok(Response::Error { message }) => {
+                eprintln!("Error: {}", message);
+                std::process::exit(1);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum ThemeName {
+    Dark,
+    Light,
+}
+
+fn parse_color(s: &str) -> Result<ColorInfo, String> {
+    ColorInfo::parse_hex(s).ok_or_else(|| format!("invalid colour {:?}, expected #rrggbb", s))
+}
+
+impl TerminalArgs {
+    fn spawn_options(self) -> SpawnOptions {
+        let mut options = SpawnOptions {
+            scrollback: self.scrollback,
+            linger: std::time::Duration::from_millis(self.linger),
+            env: self.env,
+            env_clear: self.env_clear,
+            term: self.term,
+            colorterm: self.colorterm,
+            lang: self.lang,
+            hermetic: self.hermetic,
+            kill_grace: std::time::Duration::from_millis(self.kill_grace_ms),
+            ..SpawnOptions::default()
+        };
+        if let Some(version) = self.terminal_version {
+            options.identity.version = version;
+        }
+        options.theme = match self.theme {
+            ThemeName::Dark => Theme::dark(),
+            ThemeName::Light => Theme::light(),
+        };
+        if let Some(fg) = self.fg {
+            options.theme.foreground = fg;
+        }
+        if let Some(bg) = self.bg {
+            options.theme.background = bg;
+        }
+        if let Some(cursor) = self.cursor {
+            options.theme.cursor = cursor;
+        }
+        options
+    }
+}
+
 #[derive(Subcommand)]
 enum TraceCommands {
     /// Start recording an asciicast v2 trace
@@ -153,6 +591,8 @@ enum SnapshotCommands {
         session: String,
         /// Output file path
         file: String,
+        #[command(flatten)]
+        stable: StableArgs,
     },
     /// Compare current screen against a saved baseline (exit 0 if identical, 1 if different)
     Diff {
@@ -160,14 +600,105 @@ enum SnapshotCommands {
         session: String,
         /// Path to baseline JSON file
         file: String,
+        /// How to compare cell colours
+        #[arg(long, value_enum, default_value = "resolved")]
+        colors: ColorMode,
     },
 }
 
+#[derive(Clone, ValueEnum)]
+enum ColorMode {
+    /// Compare the RGB values colours resolve to
+    Resolved,
+    /// Compare how colours were specified (default, palette index, truecolor)
+    Semantic,
+}
+
+impl From<ColorMode> for ColorCompare {
+    fn from(mode: ColorMode) -> Self {
+        match mode {
+            ColorMode::Resolved => ColorCompare::Resolved,
+            ColorMode::Semantic => ColorCompare::Semantic,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum KeyMode {
+    /// CSI sequences for cursor keys, plain characters for the keypad
+    Normal,
+    /// SS3 sequences (ESC O ...)
+    Application,
+}
+
+impl From<KeyMode> for KeyEncoding {
+    fn from(mode: KeyMode) -> Self {
+        match mode {
+            KeyMode::Normal => KeyEncoding::Normal,
+            KeyMode::Application => KeyEncoding::Application,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum EventKind {
+    Press,
+    Repeat,
+    Release,
+}
+
+#[derive(Clone, ValueEnum)]
+enum ButtonName {
+    Left,
+    Middle,
+    Right,
+}
+
+impl From<ButtonName> for MouseButton {
+    fn from(button: ButtonName) -> Self {
+        match button {
+            ButtonName::Left => MouseButton::Left,
+            ButtonName::Middle => MouseButton::Middle,
+            ButtonName::Right => MouseButton::Right,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, ValueEnum)]
+enum FocusChange {
+    /// Focus gained
+    In,
+    /// Focus lost
+    Out,
+}
+
+#[derive(Args)]
+struct ModifierArgs {
+    /// Hold shift
+    #[arg(long)]
+    shift: bool,
+    /// Hold alt
+    #[arg(long)]
+    alt: bool,
+    /// Hold ctrl
+    #[arg(long)]
+    ctrl: bool,
+    /// Hold meta
+    #[arg(long)]
+    meta: bool,
+}
+
+impl From<ModifierArgs> for Modifiers {
+    fn from(args: ModifierArgs) -> Self {
+        Modifiers { shift: args.shift, alt: args.alt, ctrl: args.ctrl, meta: args.meta }
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Spawn { command, args, cols, rows } => {
+        Commands::Spawn { command, args, cols, rows, terminal } => {
             let session_id = server::generate_session_id();
             let sock = server::socket_path(&session_id);
             let cwd = std::env::current_dir().expect("Failed to get current directory");
@@ -203,7 +734,7 @@ fn main() {
                 }
 
                 // Grandchild: this is the daemon — redirect stdio to /dev/null
-                let devnull = libc::open(b"/dev/null\0".as_ptr() as *const _, libc::O_RDWR);
+                let devnull = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
                 if devnull >= 0 {
                     libc::dup2(devnull, 0);
                     libc::dup2(devnull, 1);
@@ -214,15 +745,76 @@ fn main() {
                 }
             }
 
-            if let Err(e) = server::run_daemon(&command, &args, cols, rows, &session_id, &cwd) {
+            server::handle_shutdown_signals();
+            let options = terminal.spawn_options();
+            if let Err(e) = server::run_daemon_with_options(&command, &args, cols, rows, &session_id, &cwd, options) {
                 eprintln!("Daemon error: {}", e);
                 let _ = std::fs::remove_file(&sock);
                 std::process::exit(1);
             }
         }
 
-        Commands::Screen { session, json } => {
-            let request = Request::Screen { json };
+        Commands::Screen { session, json, scrollback, stable } => {
+            stable.wait(&session);
+            let request = if scrollback {
+                Request::Scrollback { json }
+            } else {
+                Request::Screen { json }
+            };
+            match client::send_request(&session, &request) {
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Type { session, text, delay_ms, jitter_ms } => {
+            let request = Request::Type { text, delay_ms, jitter_ms };
+            match client::send_request(&session, &request) {
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Paste { session, text, force } => {
+            let text = if text == "-" {
+                let mut buf = String::new();
+                if let Err(e) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut buf) {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+                buf
+            } else {
+                text
+            };
+            let request = Request::Paste { text, force };
+            match client::send_request(&session, &request) {
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Key { session, name, cursor_keys, keypad, event, kitty_flags, modify_other_keys } => {
+            let encoding = KeyOverride {
+                cursor: cursor_keys.map(Into::into),
+                keypad: keypad.map(Into::into),
+                kitty_flags,
+                modify_other_keys,
+            };
+            let event = match event {
+                EventKind::Press => KeyEvent::Press,
+                EventKind::Repeat => KeyEvent::Repeat,
+                EventKind::Release => KeyEvent::Release,
+            };
+            let request = Request::Key { name, event, encoding };
             match client::send_request(&session, &request) {
                 Ok(resp) => client::print_response(&resp),
                 Err(e) => {
@@ -232,8 +824,12 @@ fn main() {
             }
         }
 
-        Commands::Type { session, text } => {
-            let request = Request::Type { text };
+        Commands::Keys { session, keys, delay_ms } => {
+            let keys: Vec<String> = keys
+                .into_iter()
+                .map(|key| if key.contains(char::is_whitespace) { input::quote_key_text(&key) } else { key })
+                .collect();
+            let request = Request::Keys { sequence: keys.join(" "), delay_ms };
             match client::send_request(&session, &request) {
                 Ok(resp) => client::print_response(&resp),
                 Err(e) => {
@@ -243,8 +839,16 @@ fn main() {
             }
         }
 
-        Commands::Key { session, name } => {
-            let request = Request::Key { name };
+        Commands::Mouse { session, action, col, row, button, modifiers, clicks, interval_ms } => {
+            let request = Request::Mouse {
+                action,
+                col,
+                row,
+                button: button.into(),
+                modifiers: modifiers.into(),
+                clicks,
+                interval_ms,
+            };
             match client::send_request(&session, &request) {
                 Ok(resp) => client::print_response(&resp),
                 Err(e) => {
@@ -254,8 +858,43 @@ fn main() {
             }
         }
 
-        Commands::Mouse { session, action, col, row } => {
-            let request = Request::Mouse { action, col, row };
+        Commands::Drag { session, from_col, from_row, to_col, to_row, button, modifiers } => {
+            let request = Request::Drag {
+                from: (from_col, from_row),
+                to: (to_col, to_row),
+                button: button.into(),
+                modifiers: modifiers.into(),
+            };
+            match client::send_request(&session, &request) {
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Focus { session, focus, force } => {
+            let request = Request::Focus { gained: focus == FocusChange::In, force };
+            match client::send_request(&session, &request) {
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Find { session, pattern, regex, json } => {
+            print_locations(&session, &Request::Find { pattern, regex }, json);
+        }
+
+        Commands::FindStyle { session, style, json } => {
+            print_locations(&session, &Request::FindStyle { query: style.query() }, json);
+        }
+
+        Commands::ClickText { session, pattern, regex, index } => {
+            let request = Request::ClickText { pattern, regex, index };
             match client::send_request(&session, &request) {
                 Ok(resp) => client::print_response(&resp),
                 Err(e) => {
@@ -287,8 +926,8 @@ fn main() {
             }
         }
 
-        Commands::Kill { session } => {
-            let request = Request::Kill;
+        Commands::Kill { session, grace_ms } => {
+            let request = Request::Teardown { grace_ms };
             match client::send_request(&session, &request) {
                 Ok(resp) => client::print_response(&resp),
                 Err(e) => {
@@ -298,6 +937,73 @@ fn main() {
             }
         }
 
+        Commands::Signal { session, name } => {
+            let request = Request::Signal { name };
+            match client::send_request(&session, &request) {
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Status { session } => {
+            let request = Request::Status;
+            match client::send_request(&session, &request) {
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::WaitExit { session, timeout } => {
+            let request = Request::WaitExit { timeout };
+            match client::send_request(&session, &request) {
+                Ok(resp @ Response::Status { .. }) => {
+                    client::print_response(&resp);
+                    if let Response::Status { exit: Some(status), .. } = resp {
+                        std::process::exit(if status.signal.is_some() { 1 } else { status.code as i32 });
+                    }
+                }
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Modes { session, json } => {
+            let request = Request::Modes;
+            match client::send_request(&session, &request) {
+                Ok(Response::Modes { modes }) if json => {
+                    println!("{}", serde_json::to_string_pretty(&modes).unwrap());
+                }
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::ProcessInfo { session, json } => {
+            let request = Request::ProcessInfo;
+            match client::send_request(&session, &request) {
+                Ok(Response::ProcessInfo { info }) if json => {
+                    println!("{}", serde_json::to_string_pretty(&info).unwrap());
+                }
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
         Commands::List => {
             let sessions = client::list_sessions();
             if sessions.is_empty() {
@@ -309,16 +1015,44 @@ fn main() {
             }
         }
 
-        Commands::WaitFor { session, text, timeout } => {
-            let deadline = std::time::Instant::now() + std::time::Duration::from_millis(timeout);
-            loop {
-                let request = Request::Screen { json: false };
+        Commands::WaitFor { session, text, timeout, condition } => {
+            let request = Request::WaitFor { condition: condition.condition(text), timeout, json: condition.json };
+            match client::send_request(&session, &request) {
+                Ok(Response::Match { result, .. }) => {
+                    println!("{}", serde_json::to_string_pretty(&result).unwrap());
+                }
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::WaitStable { session, quiet_ms, timeout } => {
+            let request = Request::WaitStable { quiet_ms, timeout };
+            match client::send_request(&session, &request) {
+                Ok(resp) => client::print_response(&resp),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Assert { session, text, condition, stable, snapshot, colors } => {
+            stable.wait(&session);
+            let mut passed = true;
+            if let Some(text) = text {
+                let request = Request::Assert { condition: condition.condition(text) };
                 match client::send_request(&session, &request) {
-                    Ok(Response::Text { text: screen }) => {
-                        if screen.contains(&text) {
+                    Ok(Response::Match { text: screen, result }) => {
+                        if condition.json {
+                            println!("{}", serde_json::to_string_pretty(&result).unwrap());
+                        } else {
                             println!("{}", screen);
-                            std::process::exit(0);
                         }
+                        passed = result.matched;
                     }
                     Ok(Response::Error { message }) => {
                         eprintln!("Error: {}", message);
@@ -328,43 +1062,23 @@ fn main() {
                         eprintln!("Error: {}", e);
                         std::process::exit(1);
                     }
-                    _ => {}
-                }
-                if std::time::Instant::now() >= deadline {
-                    eprintln!("Timeout: \"{}\" not found after {}ms", text, timeout);
-                    std::process::exit(1);
-                }
-                std::thread::sleep(std::time::Duration::from_millis(50));
-            }
-        }
-
-        Commands::Assert { session, text } => {
-            let request = Request::Screen { json: false };
-            match client::send_request(&session, &request) {
-                Ok(Response::Text { text: screen }) => {
-                    println!("{}", screen);
-                    if screen.contains(&text) {
-                        std::process::exit(0);
-                    } else {
+                    _ => {
+                        eprintln!("Unexpected response");
                         std::process::exit(1);
                     }
                 }
-                Ok(Response::Error { message }) => {
-                    eprintln!("Error: {}", message);
-                    std::process::exit(1);
-                }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                    std::process::exit(1);
-                }
-                _ => {
-                    eprintln!("Unexpected response");
-                    std::process::exit(1);
+            }
+            if let Some(file) = snapshot {
+                let diff = snapshot_diff(&session, &file, colors.into());
+                if !diff.identical {
+                    println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+                    passed = false;
                 }
             }
+            std::process::exit(if passed { 0 } else { 1 });
         }
 
-        Commands::Run { command, cols, rows } => {
+        Commands::Run { command, cols, rows, terminal } => {
             let session_id = server::generate_session_id();
             let sock = server::socket_path(&session_id);
             let cwd = std::env::current_dir().expect("Failed to get current directory");
@@ -383,12 +1097,12 @@ fn main() {
                         std::thread::sleep(std::time::Duration::from_millis(50));
                     }
 
-                    let type_req = Request::Type { text: command };
+                    let type_req = Request::type_text(&command);
                     if let Err(e) = client::send_request(&session_id, &type_req) {
                         eprintln!("Error typing command: {}", e);
                         std::process::exit(1);
                     }
-                    let key_req = Request::Key { name: "enter".to_string() };
+                    let key_req = Request::key("enter");
                     if let Err(e) = client::send_request(&session_id, &key_req) {
                         eprintln!("Error sending enter: {}", e);
                         std::process::exit(1);
@@ -407,7 +1121,7 @@ fn main() {
                     std::process::exit(0);
                 }
 
-                let devnull = libc::open(b"/dev/null\0".as_ptr() as *const _, libc::O_RDWR);
+                let devnull = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
                 if devnull >= 0 {
                     libc::dup2(devnull, 0);
                     libc::dup2(devnull, 1);
@@ -418,7 +1132,9 @@ fn main() {
                 }
             }
 
-            if let Err(e) = server::run_daemon("bash", &[], cols, rows, &session_id, &cwd) {
+            server::handle_shutdown_signals();
+            let options = terminal.spawn_options();
+            if let Err(e) = server::run_daemon_with_options("bash", &[], cols, rows, &session_id, &cwd, options) {
                 eprintln!("Daemon error: {}", e);
                 let _ = std::fs::remove_file(&sock);
                 std::process::exit(1);
@@ -459,7 +1175,8 @@ fn main() {
         },
 
         Commands::Snapshot { action } => match action {
-            SnapshotCommands::Save { session, file } => {
+            SnapshotCommands::Save { session, file, stable } => {
+                stable.wait(&session);
                 let request = Request::Screen { json: true };
                 match client::send_request(&session, &request) {
                     Ok(Response::Screen { snapshot }) => {
@@ -484,47 +1201,72 @@ fn main() {
                     }
                 }
             }
-            SnapshotCommands::Diff { session, file } => {
-                let content = match std::fs::read_to_string(&file) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        eprintln!("Error reading baseline file: {}", e);
-                        std::process::exit(1);
-                    }
-                };
-                let baseline: tui_wright::screen::ScreenSnapshot = match serde_json::from_str(&content) {
-                    Ok(b) => b,
-                    Err(e) => {
-                        eprintln!("Error parsing baseline JSON: {}", e);
-                        std::process::exit(1);
-                    }
-                };
-
-                let request = Request::SnapshotDiff { baseline };
-                match client::send_request(&session, &request) {
-                    Ok(Response::Diff { diff }) => {
-                        let json = serde_json::to_string_pretty(&diff).unwrap();
-                        println!("{}", json);
-                        if diff.identical {
-                            std::process::exit(0);
-                        } else {
-                            std::process::exit(1);
-                        }
-                    }
-                    Ok(Response::Error { message }) => {
-                        eprintln!("Error: {}", message);
-                        std::process::exit(1);
-                    }
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
-                        std::process::exit(1);
-                    }
-                    _ => {
-                        eprintln!("Unexpected response");
-                        std::process::exit(1);
-                    }
+            SnapshotCommands::Diff { session, file, colors } => {
+                let diff = snapshot_diff(&session, &file, colors.into());
+                let json = serde_json::to_string_pretty(&diff).unwrap();
+                println!("{}", json);
+                if diff.identical {
+                    std::process::exit(0);
+                } else {
+                    std::process::exit(1);
                 }
             }
         },
     }
 }
+
+// Compares the session's screen against the baseline saved in `file`.
+fn snapshot_diff(session: &str, file: &str, colors: ColorCompare) -> tui_wright::diff::SnapshotDiff {
+    let content = match std::fs::read_to_string(file) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Error reading baseline file: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let baseline: tui_wright::screen::ScreenSnapshot = match serde_json::from_str(&content) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Error parsing baseline JSON: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let request = Request::SnapshotDiff { baseline, colors };
+    match client::send_request(session, &request) {
+        Ok(Response::Diff { diff }) => diff,
+        Ok(Response::Error { message }) => {
+            eprintln!("Error: {}", message);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_locations(session: &str, request: &Request, json: bool) {
+    match client::send_request(session, request) {
+        Ok(Response::Locations { locations }) => {
+            let found = !locations.is_empty();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&locations).unwrap());
+            } else {
+                client::print_response(&Response::Locations { locations });
+            }
+            if !found {
+                std::process::exit(1);
+            }
+        }
+        Ok(resp) => client::print_response(&resp),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessEntry {
+    pub pid: u32,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TeardownReport {
+    // Exited on their own within the grace period.
+    pub terminated: Vec<ProcessEntry>,
+    // Still running after the grace period and sent SIGKILL.
+    pub killed: Vec<ProcessEntry>,
+}
+
+const SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("WINCH", libc::SIGWINCH),
+];
+
+// Accepts "SIGINT", "int" or a signal number.
+pub fn parse_signal(name: &str) -> Result<libc::c_int> {
+    if let Ok(number) = name.parse::<libc::c_int>() {
+        if number > 0 && number < 65 {
+            return Ok(number);
+        }
+    }
+    let upper = name.to_uppercase();
+    let short = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|(n, _)| *n == short)
+        .map(|(_, sig)| *sig)
+        .ok_or_else(|| Error::UnknownSignal(name.to_string()))
+}
+
+pub fn signal_group(pgrp: libc::pid_t, signal: libc::c_int) -> Result<()> {
+    if unsafe { libc::kill(-pgrp, signal) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+fn group_alive(pgrp: libc::pid_t) -> bool {
+    unsafe { libc::kill(-pgrp, 0) == 0 }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: Option<u32>,
+    // The process group that currently owns the terminal, e.g. the program a
+    // shell has launched, with the command line of its leader (its executable
+    // path on macOS).
+    pub foreground_pgrp: Option<u32>,
+    pub foreground_command: Option<String>,
+    pub termios: Option<TermiosInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TermiosInfo {
+    // Line-buffered input (ICANON); false in raw and cbreak modes.
+    pub canonical: bool,
+    pub echo: bool,
+    // Ctrl+C, Ctrl+Z etc. generate signals (ISIG).
+    pub signals: bool,
+}
+
+impl TermiosInfo {
+    pub fn raw(&self) -> bool {
+        !self.canonical && !self.echo && !self.signals
+    }
+}
+
+pub fn termios(fd: libc::c_int) -> Option<TermiosInfo> {
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return None;
+    }
+    Some(TermiosInfo {
+        canonical: termios.c_lflag & libc::ICANON != 0,
+        echo: termios.c_lflag & libc::ECHO != 0,
+        signals: termios.c_lflag & libc::ISIG != 0,
+    })
+}
+
+// Live (non-zombie) processes belonging to the terminal session `sid`, found
+// by scanning /proc. This catches grandchildren that a shell has moved into
+// their own process groups for job control. Without /proc (e.g. on macOS)
+// only the session leader's own process group can be seen, which is then
+// reported under the leader's pid for as long as any of it is running.
+pub fn session_processes(sid: u32) -> Vec<ProcessEntry> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        if group_alive(sid as libc::pid_t) {
+            return vec![ProcessEntry { pid: sid, command: command_line(sid).unwrap_or_default() }];
+        }
+        return Vec::new();
+    };
+    let mut processes: Vec<ProcessEntry> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&pid| {
+            let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+                return false;
+            };
+            // Fields after the parenthesised command: state ppid pgrp session ...
+            let Some((_, rest)) = stat.rsplit_once(')') else {
+                return false;
+            };
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            fields.len() > 3 && fields[0] != "Z" && fields[3].parse() == Ok(sid)
+        })
+        .map(|pid| ProcessEntry { pid, command: command_line(pid).unwrap_or_default() })
+        .collect();
+    processes.sort_by_key(|p| p.pid);
+    processes
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn command_line(pid: u32) -> Option<String> {
+    let raw = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let args: Vec<String> = raw
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    if args.is_empty() {
+        let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        return Some(comm.trim_end().to_string());
+    }
+    Some(args.join(" "))
+}
+
+// macOS has no /proc; the executable's path is what it readily gives out.
+#[cfg(target_os = "macos")]
+pub fn command_line(pid: u32) -> Option<String> {
+    let mut buf = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+    let len = unsafe { libc::proc_pidpath(pid as libc::c_int, buf.as_mut_ptr().cast(), buf.len() as u32) };
+    if len <= 0 {
+        return None;
+    }
+    buf.truncate(len as usize);
+    Some(String::from_utf8_lossy(&buf).into_owned())
+}
+
+// Asks every process in the session to exit, waits up to `grace` and then
+// SIGKILLs whatever is left. SIGHUP is what a closing terminal sends and the
+// only one interactive shells honour; SIGCONT wakes stopped jobs so they can
+// act on it.
+pub fn teardown(sid: u32, grace: Duration) -> TeardownReport {
+    let initial = session_processes(sid);
+    for signal in [libc::SIGHUP, libc::SIGTERM, libc::SIGCONT] {
+        let _ = signal_group(sid as libc::pid_t, signal);
+        for process in &initial {
+            unsafe { libc::kill(process.pid as libc::pid_t, signal) };
+        }
+    }
+
+    let deadline = Instant::now() + grace;
+    let mut survivors = session_processes(sid);
+    while !survivors.is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+        survivors = session_processes(sid);
+    }
+    if !survivors.is_empty() {
+        let _ = signal_group(sid as libc::pid_t, libc::SIGKILL);
+        for process in &survivors {
+            unsafe { libc::kill(process.pid as libc::pid_t, libc::SIGKILL) };
+        }
+    }
+
+    let terminated = initial
+        .into_iter()
+        .filter(|p| !survivors.iter().any(|s| s.pid == p.pid))
+        .collect();
+    TeardownReport { terminated, killed: survivors }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("SIGINT").unwrap(), libc::SIGINT);
+        assert_eq!(parse_signal("term").unwrap(), libc::SIGTERM);
+        assert_eq!(parse_signal("SigWinch").unwrap(), libc::SIGWINCH);
+        assert_eq!(parse_signal("9").unwrap(), libc::SIGKILL);
+        assert!(parse_signal("SIGNOPE").is_err());
+        assert!(parse_signal("0").is_err());
+    }
+
+    #[test]
+    fn test_termios_of_pty() {
+        let pty = portable_pty::native_pty_system().openpty(portable_pty::PtySize::default()).unwrap();
+        let info = termios(pty.master.as_raw_fd().unwrap()).unwrap();
+        assert!(info.canonical && info.echo && info.signals);
+        assert!(!info.raw());
+        assert!(termios(-1).is_none());
+    }
+
+    #[test]
+    fn test_command_line() {
+        let cmd = command_line(std::process::id()).unwrap();
+        assert!(!cmd.is_empty());
+        assert!(command_line(u32::MAX).is_none());
+    }
+}
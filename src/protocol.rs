@@ -1,22 +1,138 @@
 use serde::{Deserialize, Serialize};
 
+use crate::condition::{Condition, Location, MatchResult};
 use crate::diff::SnapshotDiff;
-use crate::screen::ScreenSnapshot;
+use crate::input::{KeyEvent, KeyOverride, Modifiers, MouseButton};
+use crate::process::{ProcessInfo, TeardownReport};
+use crate::screen::{ColorCompare, ScreenSnapshot, TerminalModes};
+use crate::session::ExitStatus;
+use crate::style::StyleQuery;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Request {
     Screen { json: bool },
-    Type { text: String },
-    Key { name: String },
-    Mouse { action: String, col: u16, row: u16 },
+    Scrollback { json: bool },
+    Type {
+        text: String,
+        // When set, graphemes are sent one at a time this far apart, plus a
+        // random extra delay of up to `jitter_ms`.
+        #[serde(default)]
+        delay_ms: u64,
+        #[serde(default)]
+        jitter_ms: u64,
+    },
+    Paste {
+        text: String,
+        // Bracket the text even if the app has not enabled bracketed paste.
+        #[serde(default)]
+        force: bool,
+    },
+    Key {
+        name: String,
+        #[serde(default)]
+        event: KeyEvent,
+        #[serde(flatten)]
+        encoding: KeyOverride,
+    },
+    Keys {
+        sequence: String,
+        #[serde(default)]
+        delay_ms: u64,
+    },
+    Mouse {
+        action: String,
+        col: u16,
+        row: u16,
+        #[serde(default)]
+        button: MouseButton,
+        #[serde(default)]
+        modifiers: Modifiers,
+        // Send this many press/release pairs instead of a single press.
+        #[serde(default)]
+        clicks: Option<u32>,
+        #[serde(default)]
+        interval_ms: Option<u64>,
+    },
+    Focus {
+        gained: bool,
+        // Report the change even if the app has not enabled focus events.
+        #[serde(default)]
+        force: bool,
+    },
+    Drag {
+        from: (u16, u16),
+        to: (u16, u16),
+        #[serde(default)]
+        button: MouseButton,
+        #[serde(default)]
+        modifiers: Modifiers,
+    },
     Resize { cols: u16, rows: u16 },
     Cursor,
+    Modes,
     Kill,
+    Teardown {
+        #[serde(default)]
+        grace_ms: Option<u64>,
+    },
+    Signal { name: String },
+    Status,
+    ProcessInfo,
+    WaitExit { timeout: u64 },
     TraceStart { output: Option<String> },
     TraceStop,
     TraceMarker { label: String },
-    SnapshotDiff { baseline: ScreenSnapshot },
+    WaitFor {
+        #[serde(flatten)]
+        condition: Condition,
+        timeout: u64,
+        // Answer with the match (and any capture groups) instead of the text.
+        #[serde(default)]
+        json: bool,
+    },
+    Assert {
+        #[serde(flatten)]
+        condition: Condition,
+    },
+    WaitStable {
+        quiet_ms: u64,
+        timeout: u64,
+    },
+    Find {
+        pattern: String,
+        #[serde(default)]
+        regex: bool,
+    },
+    ClickText {
+        pattern: String,
+        #[serde(default)]
+        regex: bool,
+        // Which match to click when the pattern occurs more than once.
+        #[serde(default)]
+        index: usize,
+    },
+    FindStyle {
+        #[serde(flatten)]
+        query: StyleQuery,
+    },
+    SnapshotDiff {
+        baseline: ScreenSnapshot,
+        #[serde(default)]
+        colors: ColorCompare,
+    },
+}
+
+impl Request {
+    // A key press encoded however the application's current modes ask for.
+    pub fn key(name: &str) -> Self {
+        Request::Key { name: name.to_string(), event: KeyEvent::Press, encoding: KeyOverride::default() }
+    }
+
+    // Text written in one go, without any pacing.
+    pub fn type_text(text: &str) -> Self {
+        Request::Type { text: text.to_string(), delay_ms: 0, jitter_ms: 0 }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,6 +142,12 @@ pub enum Response {
     Text { text: String },
     Screen { snapshot: ScreenSnapshot },
     Cursor { row: u16, col: u16 },
+    Modes { modes: TerminalModes },
     Error { message: String },
     Diff { diff: SnapshotDiff },
+    Match { text: String, result: MatchResult },
+    Locations { locations: Vec<Location> },
+    Status { running: bool, pid: Option<u32>, exit: Option<ExitStatus> },
+    Teardown { report: TeardownReport },
+    ProcessInfo { info: ProcessInfo },
 }
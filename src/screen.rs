@@ -1,3 +1,5 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -7,17 +9,177 @@ pub struct ScreenSnapshot {
     pub cursor_row: u16,
     pub cursor_col: u16,
     pub cells: Vec<Vec<CellInfo>>,
+    #[serde(default)]
+    pub modes: TerminalModes,
+}
+
+// Modes the application has switched on via DECSET and friends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerminalModes {
+    pub alternate_screen: bool,
+    pub application_cursor: bool,
+    pub application_keypad: bool,
+    pub bracketed_paste: bool,
+    pub cursor_visible: bool,
+    pub mouse_mode: MouseMode,
+    pub mouse_encoding: MouseEncoding,
+    #[serde(default)]
+    pub focus_events: bool,
+    // Kitty keyboard protocol flags currently pushed by the app.
+    #[serde(default)]
+    pub keyboard_flags: u8,
+    #[serde(default)]
+    pub modify_other_keys: u8,
+}
+
+impl Default for TerminalModes {
+    fn default() -> Self {
+        TerminalModes {
+            alternate_screen: false,
+            application_cursor: false,
+            application_keypad: false,
+            bracketed_paste: false,
+            cursor_visible: true,
+            mouse_mode: MouseMode::None,
+            mouse_encoding: MouseEncoding::Default,
+            focus_events: false,
+            keyboard_flags: 0,
+            modify_other_keys: 0,
+        }
+    }
+}
+
+impl TerminalModes {
+    pub fn from_screen(screen: &vt100::Screen) -> Self {
+        TerminalModes {
+            alternate_screen: screen.alternate_screen(),
+            application_cursor: screen.application_cursor(),
+            application_keypad: screen.application_keypad(),
+            bracketed_paste: screen.bracketed_paste(),
+            cursor_visible: !screen.hide_cursor(),
+            mouse_mode: screen.mouse_protocol_mode().into(),
+            mouse_encoding: screen.mouse_protocol_encoding().into(),
+            focus_events: false,
+            keyboard_flags: 0,
+            modify_other_keys: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseMode {
+    #[default]
+    None,
+    // 9: X10, presses only.
+    Press,
+    // 1000: presses and releases.
+    PressRelease,
+    // 1002: motion while a button is held.
+    ButtonMotion,
+    // 1003: all motion.
+    AnyMotion,
+}
+
+impl From<vt100::MouseProtocolMode> for MouseMode {
+    fn from(mode: vt100::MouseProtocolMode) -> Self {
+        match mode {
+            vt100::MouseProtocolMode::None => MouseMode::None,
+            vt100::MouseProtocolMode::Press => MouseMode::Press,
+            vt100::MouseProtocolMode::PressRelease => MouseMode::PressRelease,
+            vt100::MouseProtocolMode::ButtonMotion => MouseMode::ButtonMotion,
+            vt100::MouseProtocolMode::AnyMotion => MouseMode::AnyMotion,
+        }
+    }
+}
+
+impl fmt::Display for MouseMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MouseMode::None => "none",
+            MouseMode::Press => "press",
+            MouseMode::PressRelease => "press-release",
+            MouseMode::ButtonMotion => "button-motion",
+            MouseMode::AnyMotion => "any-motion",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseEncoding {
+    #[default]
+    Default,
+    // 1005: coordinates as UTF-8 characters.
+    Utf8,
+    // 1006: CSI < b;x;y M/m.
+    Sgr,
+    // 1015: CSI b;x;y M.
+    Urxvt,
+}
+
+impl From<vt100::MouseProtocolEncoding> for MouseEncoding {
+    fn from(encoding: vt100::MouseProtocolEncoding) -> Self {
+        match encoding {
+            vt100::MouseProtocolEncoding::Default => MouseEncoding::Default,
+            vt100::MouseProtocolEncoding::Utf8 => MouseEncoding::Utf8,
+            vt100::MouseProtocolEncoding::Sgr => MouseEncoding::Sgr,
+        }
+    }
+}
+
+impl fmt::Display for MouseEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MouseEncoding::Default => "default",
+            MouseEncoding::Utf8 => "utf8",
+            MouseEncoding::Sgr => "sgr",
+            MouseEncoding::Urxvt => "urxvt",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// A rectangle of the screen; all bounds are inclusive and clamped to the
+// screen size when used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub top: u16,
+    pub left: u16,
+    pub bottom: u16,
+    pub right: u16,
+}
+
+impl Region {
+    pub fn rows(top: u16, bottom: u16) -> Self {
+        Region { top, left: 0, bottom, right: u16::MAX }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CellInfo {
     pub char: String,
+    #[serde(deserialize_with = "deserialize_fg")]
     pub fg: ColorInfo,
+    #[serde(deserialize_with = "deserialize_bg")]
     pub bg: ColorInfo,
     pub bold: bool,
     pub italic: bool,
     pub underline: bool,
     pub inverse: bool,
+    #[serde(default)]
+    pub dim: bool,
+    #[serde(default)]
+    pub blink: bool,
+    #[serde(default)]
+    pub strikethrough: bool,
+    #[serde(default)]
+    pub wide: bool,
+    #[serde(default)]
+    pub wide_continuation: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hyperlink: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -25,23 +187,184 @@ pub struct ColorInfo {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    #[serde(default)]
+    pub kind: ColorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorKind {
+    Default,
+    Indexed(u8),
+    #[default]
+    Rgb,
+}
+
+// A cell colour as stored in a snapshot file. Snapshots saved before colour
+// kinds were recorded have no `kind`; there the default colours were always
+// stored as the values below, so those are taken to be the default.
+#[derive(Deserialize)]
+struct StoredColor {
+    r: u8,
+    g: u8,
+    b: u8,
+    #[serde(default)]
+    kind: Option<ColorKind>,
+}
+
+impl StoredColor {
+    fn into_color(self, default: ColorInfo) -> ColorInfo {
+        let kind = match self.kind {
+            Some(kind) => kind,
+            None if (self.r, self.g, self.b) == (default.r, default.g, default.b) => ColorKind::Default,
+            None => ColorKind::Rgb,
+        };
+        ColorInfo { r: self.r, g: self.g, b: self.b, kind }
+    }
+}
+
+fn deserialize_fg<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<ColorInfo, D::Error> {
+    let stored = StoredColor::deserialize(deserializer)?;
+    Ok(stored.into_color(ColorInfo::from_vt100_color(vt100::Color::Default)))
+}
+
+fn deserialize_bg<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<ColorInfo, D::Error> {
+    let stored = StoredColor::deserialize(deserializer)?;
+    Ok(stored.into_color(ColorInfo::from_vt100_bg(vt100::Color::Default)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorCompare {
+    // Compare the RGB value each colour resolved to under the session theme.
+    #[default]
+    Resolved,
+    // Compare how each colour was specified: default, palette index or truecolor.
+    Semantic,
 }
 
 impl ColorInfo {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        ColorInfo { r, g, b, kind: ColorKind::Rgb }
+    }
+
     pub fn from_vt100_color(color: vt100::Color) -> Self {
         match color {
-            vt100::Color::Default => ColorInfo { r: 255, g: 255, b: 255 },
-            vt100::Color::Idx(idx) => idx_to_rgb(idx),
-            vt100::Color::Rgb(r, g, b) => ColorInfo { r, g, b },
+            vt100::Color::Default => ColorInfo { kind: ColorKind::Default, ..ColorInfo::rgb(255, 255, 255) },
+            vt100::Color::Idx(idx) => ColorInfo { kind: ColorKind::Indexed(idx), ..idx_to_rgb(idx) },
+            vt100::Color::Rgb(r, g, b) => ColorInfo::rgb(r, g, b),
         }
     }
 
     pub fn from_vt100_bg(color: vt100::Color) -> Self {
         match color {
-            vt100::Color::Default => ColorInfo { r: 0, g: 0, b: 0 },
-            vt100::Color::Idx(idx) => idx_to_rgb(idx),
-            vt100::Color::Rgb(r, g, b) => ColorInfo { r, g, b },
+            vt100::Color::Default => ColorInfo { kind: ColorKind::Default, ..ColorInfo::rgb(0, 0, 0) },
+            vt100::Color::Idx(idx) => ColorInfo { kind: ColorKind::Indexed(idx), ..idx_to_rgb(idx) },
+            vt100::Color::Rgb(r, g, b) => ColorInfo::rgb(r, g, b),
+        }
+    }
+
+    pub fn parse_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
         }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(ColorInfo::rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    // X11 colour spec as used in OSC colour query replies, e.g. rgb:cdcd/0000/0000
+    pub fn to_x11_spec(&self) -> String {
+        format!(
+            "rgb:{:02x}{:02x}/{:02x}{:02x}/{:02x}{:02x}",
+            self.r, self.r, self.g, self.g, self.b, self.b
+        )
+    }
+
+    pub fn matches(&self, other: &ColorInfo, compare: ColorCompare) -> bool {
+        let same_rgb = (self.r, self.g, self.b) == (other.r, other.g, other.b);
+        match compare {
+            ColorCompare::Resolved => same_rgb,
+            ColorCompare::Semantic => match (self.kind, other.kind) {
+                (ColorKind::Rgb, ColorKind::Rgb) => same_rgb,
+                (a, b) => a == b,
+            },
+        }
+    }
+}
+
+impl CellInfo {
+    pub fn matches(&self, other: &CellInfo, compare: ColorCompare) -> bool {
+        self.char == other.char
+            && self.fg.matches(&other.fg, compare)
+            && self.bg.matches(&other.bg, compare)
+            && self.bold == other.bold
+            && self.italic == other.italic
+            && self.underline == other.underline
+            && self.inverse == other.inverse
+            && self.dim == other.dim
+            && self.blink == other.blink
+            && self.strikethrough == other.strikethrough
+            && self.wide == other.wide
+            && self.wide_continuation == other.wide_continuation
+            && self.hyperlink == other.hyperlink
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub foreground: ColorInfo,
+    pub background: ColorInfo,
+    pub cursor: ColorInfo,
+    pub palette: Vec<ColorInfo>,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Theme {
+            foreground: ColorInfo::rgb(255, 255, 255),
+            background: ColorInfo::rgb(0, 0, 0),
+            cursor: ColorInfo::rgb(255, 255, 255),
+            palette: (0..=255).map(idx_to_rgb).collect(),
+        }
+    }
+
+    pub fn light() -> Self {
+        Theme {
+            foreground: ColorInfo::rgb(0, 0, 0),
+            background: ColorInfo::rgb(255, 255, 255),
+            cursor: ColorInfo::rgb(0, 0, 0),
+            palette: (0..=255).map(idx_to_rgb).collect(),
+        }
+    }
+
+    pub fn palette_color(&self, idx: u8) -> ColorInfo {
+        self.palette
+            .get(idx as usize)
+            .cloned()
+            .unwrap_or_else(|| idx_to_rgb(idx))
+    }
+
+    pub fn resolve_fg(&self, color: vt100::Color) -> ColorInfo {
+        self.resolve(color, &self.foreground)
+    }
+
+    pub fn resolve_bg(&self, color: vt100::Color) -> ColorInfo {
+        self.resolve(color, &self.background)
+    }
+
+    fn resolve(&self, color: vt100::Color, default: &ColorInfo) -> ColorInfo {
+        match color {
+            vt100::Color::Default => ColorInfo { kind: ColorKind::Default, ..default.clone() },
+            vt100::Color::Idx(idx) => ColorInfo { kind: ColorKind::Indexed(idx), ..self.palette_color(idx) },
+            vt100::Color::Rgb(r, g, b) => ColorInfo::rgb(r, g, b),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
     }
 }
 
@@ -67,7 +390,7 @@ fn idx_to_rgb(idx: u8) -> ColorInfo {
 
     if idx < 16 {
         let (r, g, b) = BASIC[idx as usize];
-        return ColorInfo { r, g, b };
+        return ColorInfo::rgb(r, g, b);
     }
 
     if idx < 232 {
@@ -75,35 +398,23 @@ fn idx_to_rgb(idx: u8) -> ColorInfo {
         let r = (idx / 36) * 51;
         let g = ((idx % 36) / 6) * 51;
         let b = (idx % 6) * 51;
-        return ColorInfo { r, g, b };
+        return ColorInfo::rgb(r, g, b);
     }
 
     let gray = 8 + (idx - 232) * 10;
-    ColorInfo { r: gray, g: gray, b: gray }
+    ColorInfo::rgb(gray, gray, gray)
 }
 
 pub fn from_screen(screen: &vt100::Screen) -> ScreenSnapshot {
+    from_screen_with_theme(screen, &Theme::default())
+}
+
+pub fn from_screen_with_theme(screen: &vt100::Screen, theme: &Theme) -> ScreenSnapshot {
     let size = screen.size();
     let (rows, cols) = (size.0, size.1);
     let cursor = screen.cursor_position();
 
-    let mut cells = Vec::with_capacity(rows as usize);
-    for row in 0..rows {
-        let mut row_cells = Vec::with_capacity(cols as usize);
-        for col in 0..cols {
-            let cell = screen.cell(row, col).unwrap();
-            row_cells.push(CellInfo {
-                char: cell.contents(),
-                fg: ColorInfo::from_vt100_color(cell.fgcolor()),
-                bg: ColorInfo::from_vt100_bg(cell.bgcolor()),
-                bold: cell.bold(),
-                italic: cell.italic(),
-                underline: cell.underline(),
-                inverse: cell.inverse(),
-            });
-        }
-        cells.push(row_cells);
-    }
+    let cells = (0..rows).map(|row| row_cells(screen, row, theme)).collect();
 
     ScreenSnapshot {
         rows,
@@ -111,26 +422,77 @@ pub fn from_screen(screen: &vt100::Screen) -> ScreenSnapshot {
         cursor_row: cursor.0,
         cursor_col: cursor.1,
         cells,
+        modes: TerminalModes::from_screen(screen),
     }
 }
 
+pub fn row_cells(screen: &vt100::Screen, row: u16, theme: &Theme) -> Vec<CellInfo> {
+    let cols = screen.size().1;
+    let mut row_cells = Vec::with_capacity(cols as usize);
+    for col in 0..cols {
+        let cell = screen.cell(row, col).unwrap();
+        row_cells.push(CellInfo {
+            char: cell.contents().to_string(),
+            fg: theme.resolve_fg(cell.fgcolor()),
+            bg: theme.resolve_bg(cell.bgcolor()),
+            bold: cell.bold(),
+            italic: cell.italic(),
+            underline: cell.underline(),
+            inverse: cell.inverse(),
+            dim: cell.dim(),
+            blink: false,
+            strikethrough: false,
+            wide: cell.is_wide(),
+            wide_continuation: cell.is_wide_continuation(),
+            hyperlink: None,
+        });
+    }
+    row_cells
+}
+
 pub fn screen_text(screen: &vt100::Screen) -> String {
-    let size = screen.size();
-    let mut lines = Vec::new();
-    for row in 0..size.0 {
-        let mut line = String::new();
-        for col in 0..size.1 {
-            if let Some(cell) = screen.cell(row, col) {
-                let contents = cell.contents();
-                if contents.is_empty() {
+    let mut lines: Vec<String> = (0..screen.size().0).map(|row| row_text(screen, row)).collect();
+
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+pub fn row_text(screen: &vt100::Screen, row: u16) -> String {
+    let mut line = String::new();
+    for col in 0..screen.size().1 {
+        if let Some(cell) = screen.cell(row, col) {
+            let contents = cell.contents();
+            if contents.is_empty() {
+                line.push(' ');
+            } else {
+                line.push_str(contents);
+            }
+        }
+    }
+    line.trim_end().to_string()
+}
+
+pub fn region_text(snapshot: &ScreenSnapshot, region: &Region) -> String {
+    let mut lines: Vec<String> = snapshot
+        .cells
+        .iter()
+        .enumerate()
+        .filter(|(row, _)| (region.top as usize..=region.bottom as usize).contains(row))
+        .map(|(_, cells)| {
+            let mut line = String::new();
+            for cell in cells.iter().skip(region.left as usize).take((region.right as usize + 1).saturating_sub(region.left as usize)) {
+                if cell.char.is_empty() {
                     line.push(' ');
                 } else {
-                    line.push_str(&contents);
+                    line.push_str(&cell.char);
                 }
             }
-        }
-        lines.push(line.trim_end().to_string());
-    }
+            line.trim_end().to_string()
+        })
+        .collect();
 
     while lines.last().is_some_and(|l| l.is_empty()) {
         lines.pop();
@@ -164,6 +526,62 @@ mod tests {
         assert_eq!((c.r, c.g, c.b), (238, 238, 238));
     }
 
+    #[test]
+    fn test_theme_resolves_default_colors() {
+        let mut parser = vt100::Parser::new(2, 10, 0);
+        parser.process(b"a\x1b[31mb");
+        let dark = from_screen_with_theme(parser.screen(), &Theme::dark());
+        let light = from_screen_with_theme(parser.screen(), &Theme::light());
+        assert!(dark.cells[0][0].fg.matches(&ColorInfo::rgb(255, 255, 255), ColorCompare::Resolved));
+        assert!(light.cells[0][0].fg.matches(&ColorInfo::rgb(0, 0, 0), ColorCompare::Resolved));
+        assert!(light.cells[0][0].bg.matches(&ColorInfo::rgb(255, 255, 255), ColorCompare::Resolved));
+        assert_eq!(dark.cells[0][1].fg, light.cells[0][1].fg);
+    }
+
+    #[test]
+    fn test_color_kinds_preserved() {
+        let mut parser = vt100::Parser::new(2, 10, 0);
+        parser.process(b"a\x1b[31mb\x1b[38;2;205;0;0mc\x1b[97md");
+        let snap = from_screen(parser.screen());
+        let row = &snap.cells[0];
+        assert_eq!(row[0].fg.kind, ColorKind::Default);
+        assert_eq!(row[1].fg.kind, ColorKind::Indexed(1));
+        assert_eq!(row[2].fg.kind, ColorKind::Rgb);
+        assert_eq!(row[0].bg.kind, ColorKind::Default);
+
+        assert!(row[1].fg.matches(&row[2].fg, ColorCompare::Resolved));
+        assert!(!row[1].fg.matches(&row[2].fg, ColorCompare::Semantic));
+        assert!(row[0].fg.matches(&row[3].fg, ColorCompare::Resolved));
+        assert!(!row[0].fg.matches(&row[3].fg, ColorCompare::Semantic));
+    }
+
+    #[test]
+    fn test_semantic_compare_ignores_theme() {
+        let mut parser = vt100::Parser::new(2, 10, 0);
+        parser.process(b"a");
+        let dark = from_screen_with_theme(parser.screen(), &Theme::dark());
+        let light = from_screen_with_theme(parser.screen(), &Theme::light());
+        assert!(dark.cells[0][0].matches(&light.cells[0][0], ColorCompare::Semantic));
+        assert!(!dark.cells[0][0].matches(&light.cells[0][0], ColorCompare::Resolved));
+    }
+
+    #[test]
+    fn test_color_kind_defaults_to_rgb() {
+        let c: ColorInfo = serde_json::from_str(r#"{"r":1,"g":2,"b":3}"#).unwrap();
+        assert_eq!(c, ColorInfo::rgb(1, 2, 3));
+        let json = serde_json::to_string(&ColorInfo { kind: ColorKind::Indexed(4), ..ColorInfo::rgb(0, 0, 238) }).unwrap();
+        assert_eq!(json, r#"{"r":0,"g":0,"b":238,"kind":{"indexed":4}}"#);
+    }
+
+    #[test]
+    fn test_color_hex_and_x11_spec() {
+        let c = ColorInfo::parse_hex("#cd0080").unwrap();
+        assert_eq!(c, ColorInfo::rgb(205, 0, 128));
+        assert_eq!(c.to_x11_spec(), "rgb:cdcd/0000/8080");
+        assert!(ColorInfo::parse_hex("12345").is_none());
+        assert!(ColorInfo::parse_hex("zz0000").is_none());
+    }
+
     #[test]
     fn test_from_screen() {
         let mut parser = vt100::Parser::new(24, 80, 0);
@@ -173,6 +591,23 @@ mod tests {
         assert_eq!(snap.cols, 80);
         assert_eq!(snap.cells[0][0].char, "H");
         assert_eq!(snap.cells[0][4].char, "o");
+        assert_eq!(snap.modes, TerminalModes::default());
+    }
+
+    #[test]
+    fn test_terminal_modes() {
+        let mut parser = vt100::Parser::new(4, 10, 0);
+        parser.process(b"\x1b[?1049h\x1b[?1h\x1b=\x1b[?2004h\x1b[?1002h\x1b[?1006h\x1b[?25l");
+        let modes = from_screen(parser.screen()).modes;
+        assert!(modes.alternate_screen && modes.application_cursor && modes.application_keypad);
+        assert!(modes.bracketed_paste && !modes.cursor_visible);
+        assert_eq!(modes.mouse_mode, MouseMode::ButtonMotion);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::Sgr);
+
+        parser.process(b"\x1b[?1049l\x1b[?1002l\x1b[?25h");
+        let modes = TerminalModes::from_screen(parser.screen());
+        assert!(!modes.alternate_screen && modes.cursor_visible);
+        assert_eq!(modes.mouse_mode, MouseMode::None);
     }
 
     #[test]
@@ -183,6 +618,17 @@ mod tests {
         assert!(text.starts_with("Hello, world!"));
     }
 
+    #[test]
+    fn test_region_text() {
+        let mut parser = vt100::Parser::new(4, 10, 0);
+        parser.process(b"abcdef\r\nghijkl\r\n\xe4\xb8\xadxyz");
+        let snap = from_screen(parser.screen());
+        let all = Region::rows(0, u16::MAX);
+        assert_eq!(region_text(&snap, &all), screen_text(parser.screen()));
+        assert_eq!(region_text(&snap, &Region::rows(1, 1)), "ghijkl");
+        assert_eq!(region_text(&snap, &Region { top: 0, left: 2, bottom: 1, right: 3 }), "cd\nij");
+    }
+
     #[test]
     fn test_snapshot_serialization() {
         let mut parser = vt100::Parser::new(4, 10, 0);
@@ -190,5 +636,26 @@ mod tests {
         let snap = from_screen(parser.screen());
         let json = serde_json::to_string(&snap).unwrap();
         let _: ScreenSnapshot = serde_json::from_str(&json).unwrap();
+        assert!(json.contains(r#""mouse_mode":"none""#));
+
+        let old: ScreenSnapshot = serde_json::from_str(r#"{"rows":0,"cols":0,"cursor_row":0,"cursor_col":0,"cells":[]}"#).unwrap();
+        assert!(old.modes.cursor_visible);
+    }
+
+    #[test]
+    fn test_cell_colors_without_kind() {
+        let cell = |fg: &str, bg: &str| -> CellInfo {
+            let json = format!(
+                r#"{{"char":"x","fg":{},"bg":{},"bold":false,"italic":false,"underline":false,"inverse":false}}"#,
+                fg, bg
+            );
+            serde_json::from_str(&json).unwrap()
+        };
+        let old = cell(r#"{"r":255,"g":255,"b":255}"#, r#"{"r":0,"g":0,"b":0}"#);
+        assert_eq!((old.fg.kind, old.bg.kind), (ColorKind::Default, ColorKind::Default));
+        let old = cell(r#"{"r":205,"g":0,"b":0}"#, r#"{"r":255,"g":255,"b":255}"#);
+        assert_eq!((old.fg.kind, old.bg.kind), (ColorKind::Rgb, ColorKind::Rgb));
+        let new = cell(r#"{"r":255,"g":255,"b":255,"kind":"rgb"}"#, r#"{"r":0,"g":0,"b":0,"kind":{"indexed":0}}"#);
+        assert_eq!((new.fg.kind, new.bg.kind), (ColorKind::Rgb, ColorKind::Indexed(0)));
     }
 }
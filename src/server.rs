@@ -1,10 +1,17 @@
 use std::io::{BufRead, BufReader, Write};
-use std::os::unix::net::UnixListener;
-use std::path::PathBuf;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use crate::error::Result;
+use crate::condition::Condition;
+use crate::error::{Error, Result};
+use crate::input::{self, KeyEvent, MouseAction};
 use crate::protocol::{Request, Response};
-use crate::session::Session;
+use crate::session::{ExitWatch, Input, Session, SpawnOptions};
+use crate::wait::Watcher;
 
 pub fn socket_path(session_id: &str) -> PathBuf {
     let tmp = std::env::temp_dir();
@@ -17,16 +24,74 @@ pub fn generate_session_id() -> String {
     format!("{:06x}", rng.gen::<u32>() & 0xFFFFFF)
 }
 
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_shutdown(_signal: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+// Installed by the daemon process so that SIGTERM, SIGINT or SIGHUP tear the
+// session down before exiting rather than orphaning the child.
+pub fn handle_shutdown_signals() {
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        unsafe { libc::signal(signal, request_shutdown as *const () as libc::sighandler_t) };
+    }
+}
+
 pub fn run_daemon(command: &str, args: &[String], cols: u16, rows: u16, session_id: &str, cwd: &std::path::Path) -> Result<()> {
+    run_daemon_with_options(command, args, cols, rows, session_id, cwd, SpawnOptions::default())
+}
+
+pub fn run_daemon_with_options(
+    command: &str,
+    args: &[String],
+    cols: u16,
+    rows: u16,
+    session_id: &str,
+    cwd: &std::path::Path,
+    options: SpawnOptions,
+) -> Result<()> {
     let sock = socket_path(session_id);
     if sock.exists() {
         std::fs::remove_file(&sock)?;
     }
 
     let listener = UnixListener::bind(&sock)?;
-    let mut session = Session::spawn(command, args, cols, rows, cwd)?;
+    let linger = options.linger;
+    let mut session = Session::spawn_with_options(command, args, cols, rows, cwd, options)?;
+
+    // Set once the daemon should stop, by a shutdown signal or once the child
+    // has been gone for `linger`.
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let shutdown_clone = Arc::clone(&shutdown);
+    let wake = sock.clone();
+    thread::spawn(move || {
+        while !shutdown_clone.load(Ordering::SeqCst) {
+            if SHUTDOWN.load(Ordering::SeqCst) {
+                stop(&shutdown_clone, &wake);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+
+    let exit = session.exit_watch();
+    let shutdown_clone = Arc::clone(&shutdown);
+    let wake = sock.clone();
+    thread::spawn(move || {
+        while exit.wait(Duration::from_millis(100)).is_err() {
+            if shutdown_clone.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+        thread::sleep(linger);
+        stop(&shutdown_clone, &wake);
+    });
 
     for stream in listener.incoming() {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
         let stream = match stream {
             Ok(s) => s,
             Err(_) => continue,
@@ -47,42 +112,152 @@ pub fn run_daemon(command: &str, args: &[String], cols: u16, rows: u16, session_
             }
         };
 
-        if !session.is_alive() {
-            let is_kill = matches!(&request, Request::Kill);
-            if !is_kill {
-                let resp = Response::Error { message: "Child process has exited".to_string() };
-                let _ = write_response(&stream, &resp);
-            } else {
-                let _ = write_response(&stream, &Response::Ok);
+        // Waits are answered from their own thread so the daemon keeps serving
+        // input requests while a client is blocked on one.
+        if matches!(request, Request::WaitFor { .. } | Request::WaitStable { .. }) {
+            let watcher = session.watcher();
+            thread::spawn(move || {
+                let response = handle_wait(&watcher, request);
+                let _ = write_response(&stream, &response);
+            });
+            continue;
+        }
+        if let Request::WaitExit { timeout } = request {
+            let exit = session.exit_watch();
+            let pid = session.pid();
+            thread::spawn(move || {
+                let response = wait_exit(&exit, pid, timeout);
+                let _ = write_response(&stream, &response);
+            });
+            continue;
+        }
+
+        // Paced input sleeps between writes, so it is sent from its own thread
+        // as well rather than holding up every other client.
+        if is_paced(&request) {
+            if let Some(response) = start_input(&session, &request) {
+                let _ = write_response(&stream, &response);
+                continue;
             }
-            let _ = session.trace_stop();
-            let _ = std::fs::remove_file(&sock);
-            break;
+            let input = session.input();
+            thread::spawn(move || {
+                let response = handle_paced(&input, request);
+                let _ = write_response(&stream, &response);
+            });
+            continue;
         }
 
+        let last = matches!(request, Request::Kill | Request::Teardown { .. });
         let response = handle_request(&mut session, request);
         let _ = write_response(&stream, &response);
 
-        if line.trim().contains("\"Kill\"") || line.trim().contains("\"type\":\"Kill\"") {
-            let _ = session.trace_stop();
-            let _ = std::fs::remove_file(&sock);
+        if last {
             break;
         }
     }
 
+    shutdown.store(true, Ordering::SeqCst);
+    session.teardown(session.kill_grace());
+    let _ = session.trace_stop();
+    let _ = std::fs::remove_file(&sock);
     Ok(())
 }
 
-fn handle_request(session: &mut Session, request: Request) -> Response {
-    match &request {
-        Request::Key { name } => session.trace_marker(&format!("key {}", name)),
-        Request::Type { text } => session.trace_marker(&format!("type {:?}", text)),
-        Request::Mouse { action, col, row } => {
-            session.trace_marker(&format!("mouse {} {},{}", action, col, row));
+// Asks the accept loop to return. It only looks at the flag once a connection
+// comes in, so make one.
+fn stop(shutdown: &AtomicBool, sock: &Path) {
+    shutdown.store(true, Ordering::SeqCst);
+    let _ = UnixStream::connect(sock);
+}
+
+// Marks input requests in the trace and turns them away once the child has
+// gone, before they are handled here or on their own thread.
+fn start_input(session: &Session, request: &Request) -> Option<Response> {
+    match request {
+        Request::Key { name, event, .. } => {
+            let suffix = match event {
+                KeyEvent::Press => "",
+                KeyEvent::Repeat => " (repeat)",
+                KeyEvent::Release => " (release)",
+            };
+            session.trace_marker(&format!("key {}{}", name, suffix));
+        }
+        Request::Type { text, .. } => session.trace_marker(&format!("type {:?}", text)),
+        Request::Keys { sequence, .. } => session.trace_marker(&format!("keys {}", sequence)),
+        Request::Paste { text, .. } => session.trace_marker(&format!("paste {:?}", text)),
+        Request::Mouse { action, col, row, button, clicks, .. } => {
+            let count = match clicks {
+                Some(n) if *n > 1 => format!(" x{}", n),
+                _ => String::new(),
+            };
+            session.trace_marker(&format!("mouse {}{} {} {},{}", action, count, button, col, row));
         }
+        Request::Drag { from, to, button, .. } => {
+            session.trace_marker(&format!("drag {} {},{} -> {},{}", button, from.0, from.1, to.0, to.1));
+        }
+        Request::Focus { gained, .. } => session.trace_marker(if *gained { "focus in" } else { "focus out" }),
+        Request::ClickText { pattern, .. } => session.trace_marker(&format!("click-text {:?}", pattern)),
+        Request::Signal { name } => session.trace_marker(&format!("signal {}", name)),
         _ => {}
     }
 
+    // The daemon outlives the child so its final screen and exit status can
+    // still be queried, but there is nobody left to send input to.
+    let needs_child = matches!(
+        request,
+        Request::Type { .. }
+            | Request::Paste { .. }
+            | Request::Key { .. }
+            | Request::Keys { .. }
+            | Request::Mouse { .. }
+            | Request::Drag { .. }
+            | Request::Focus { .. }
+            | Request::ClickText { .. }
+            | Request::Signal { .. }
+    );
+    if needs_child && !session.is_alive() {
+        return Some(Response::Error { message: "Child process has exited".to_string() });
+    }
+    None
+}
+
+// Input that sleeps between writes.
+fn is_paced(request: &Request) -> bool {
+    match request {
+        Request::Type { delay_ms, jitter_ms, .. } => *delay_ms > 0 || *jitter_ms > 0,
+        Request::Keys { delay_ms, .. } => *delay_ms > 0,
+        Request::Mouse { clicks, .. } => clicks.is_some(),
+        _ => false,
+    }
+}
+
+fn handle_paced(input: &Input, request: Request) -> Response {
+    let result = match request {
+        Request::Type { text, delay_ms, jitter_ms } => {
+            input.type_text_paced(&text, Duration::from_millis(delay_ms), Duration::from_millis(jitter_ms))
+        }
+        Request::Keys { sequence, delay_ms } => input.send_keys(&sequence, Duration::from_millis(delay_ms)),
+        Request::Mouse { action, col, row, button, modifiers, clicks: Some(count), interval_ms } => {
+            let interval = interval_ms.map(Duration::from_millis).unwrap_or(input::DEFAULT_CLICK_INTERVAL);
+            match input::parse_mouse_action(&action, button) {
+                Ok(MouseAction::Press(_)) => input.click(col, row, button, modifiers, count, interval),
+                Ok(_) => Err(Error::InvalidArgument(format!("{} cannot be sent as clicks", action))),
+                Err(e) => Err(e),
+            }
+        }
+        _ => return Response::Error { message: "Not a paced input request".to_string() },
+    };
+    match result {
+        Ok(()) => Response::Ok,
+        Err(e) => Response::Error { message: e.to_string() },
+    }
+}
+
+fn handle_request(session: &mut Session, request: Request) -> Response {
+    if let Some(response) = start_input(session, &request) {
+        return response;
+    }
+
     match request {
         Request::Screen { json } => {
             if json {
@@ -91,15 +266,43 @@ fn handle_request(session: &mut Session, request: Request) -> Response {
                 Response::Text { text: session.screen_text() }
             }
         }
-        Request::Type { text } => match session.type_text(&text) {
+        Request::Scrollback { json } => {
+            if json {
+                Response::Screen { snapshot: session.scrollback_snapshot() }
+            } else {
+                Response::Text { text: session.scrollback_text() }
+            }
+        }
+        Request::Type { text, .. } => match session.type_text(&text) {
+            Ok(()) => Response::Ok,
+            Err(e) => Response::Error { message: e.to_string() },
+        },
+        Request::Paste { text, force } => match session.paste(&text, force) {
+            Ok(()) => Response::Ok,
+            Err(e) => Response::Error { message: e.to_string() },
+        },
+        Request::Key { name, event, encoding } => match session.send_key_by_name_with(&name, event, encoding) {
             Ok(()) => Response::Ok,
             Err(e) => Response::Error { message: e.to_string() },
         },
-        Request::Key { name } => match session.send_key_by_name(&name) {
+        Request::Keys { sequence, delay_ms } => match session.send_keys(&sequence, Duration::from_millis(delay_ms)) {
             Ok(()) => Response::Ok,
             Err(e) => Response::Error { message: e.to_string() },
         },
-        Request::Mouse { action, col, row } => match session.send_mouse(&action, col, row) {
+        Request::Mouse { clicks: None, interval_ms: Some(_), .. } => Response::Error {
+            message: Error::InvalidArgument("interval_ms only applies with clicks".to_string()).to_string(),
+        },
+        Request::Mouse { action, col, row, button, modifiers, .. } => {
+            match session.send_mouse(&action, col, row, button, modifiers) {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+        Request::Drag { from, to, button, modifiers } => match session.drag(from, to, button, modifiers) {
+            Ok(()) => Response::Ok,
+            Err(e) => Response::Error { message: e.to_string() },
+        },
+        Request::Focus { gained, force } => match session.focus(gained, force) {
             Ok(()) => Response::Ok,
             Err(e) => Response::Error { message: e.to_string() },
         },
@@ -111,10 +314,26 @@ fn handle_request(session: &mut Session, request: Request) -> Response {
             let (row, col) = session.cursor_position();
             Response::Cursor { row, col }
         }
+        Request::Modes => Response::Modes { modes: session.modes() },
         Request::Kill => match session.kill() {
             Ok(()) => Response::Ok,
             Err(e) => Response::Error { message: e.to_string() },
         },
+        Request::Teardown { grace_ms } => {
+            let grace = grace_ms.map(Duration::from_millis).unwrap_or(session.kill_grace());
+            Response::Teardown { report: session.teardown(grace) }
+        }
+        Request::Signal { name } => match session.signal(&name) {
+            Ok(()) => Response::Ok,
+            Err(e) => Response::Error { message: e.to_string() },
+        },
+        Request::Status => Response::Status {
+            running: session.is_alive(),
+            pid: session.pid(),
+            exit: session.exit_status(),
+        },
+        Request::ProcessInfo => Response::ProcessInfo { info: session.process_info() },
+        Request::WaitExit { timeout } => wait_exit(&session.exit_watch(), session.pid(), timeout),
         Request::TraceStart { output } => {
             let path = match output {
                 Some(p) => PathBuf::from(p),
@@ -136,14 +355,69 @@ fn handle_request(session: &mut Session, request: Request) -> Response {
             session.trace_marker(&label);
             Response::Ok
         }
-        Request::SnapshotDiff { baseline } => {
+        Request::WaitFor { .. } | Request::WaitStable { .. } => handle_wait(&session.watcher(), request),
+        Request::Assert { condition } => match session.check(&condition) {
+            Ok((text, result)) => Response::Match { text, result },
+            Err(e) => Response::Error { message: e.to_string() },
+        },
+        Request::Find { pattern, regex } => {
+            let condition = Condition { text: pattern, regex, ..Default::default() };
+            match session.find(&condition) {
+                Ok(locations) => Response::Locations { locations },
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+        Request::ClickText { pattern, regex, index } => {
+            let condition = Condition { text: pattern, regex, ..Default::default() };
+            match session.click_text(&condition, index) {
+                Ok(location) => Response::Locations { locations: vec![location] },
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+        Request::FindStyle { query } => Response::Locations { locations: session.find_style(&query) },
+        Request::SnapshotDiff { baseline, colors } => {
             let current = session.screen_snapshot();
-            let diff_result = crate::diff::compute_diff(&baseline, &current);
+            let diff_result = crate::diff::compute_diff_with(&baseline, &current, colors);
             Response::Diff { diff: diff_result }
         }
     }
 }
 
+fn wait_exit(exit: &ExitWatch, pid: Option<u32>, timeout: u64) -> Response {
+    match exit.wait(Duration::from_millis(timeout)) {
+        Ok(status) => Response::Status { running: false, pid, exit: Some(status) },
+        Err(Error::Timeout(ms)) => Response::Error {
+            message: format!("Timeout: child still running after {}ms", ms),
+        },
+        Err(e) => Response::Error { message: e.to_string() },
+    }
+}
+
+fn handle_wait(watcher: &Watcher, request: Request) -> Response {
+    match request {
+        Request::WaitFor { condition, timeout, json } => {
+            match watcher.wait_for(&condition, Duration::from_millis(timeout)) {
+                Ok((text, result)) if json => Response::Match { text, result },
+                Ok((text, _)) => Response::Text { text },
+                Err(Error::Timeout(ms)) => Response::Error {
+                    message: format!("Timeout: {} not found after {}ms", condition, ms),
+                },
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+        Request::WaitStable { quiet_ms, timeout } => {
+            match watcher.wait_stable(Duration::from_millis(quiet_ms), Duration::from_millis(timeout)) {
+                Ok(()) => Response::Ok,
+                Err(Error::Timeout(ms)) => Response::Error {
+                    message: format!("Timeout: screen not stable for {}ms after {}ms", quiet_ms, ms),
+                },
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+        _ => Response::Error { message: "Not a wait request".to_string() },
+    }
+}
+
 fn write_response(mut stream: &std::os::unix::net::UnixStream, response: &Response) -> Result<()> {
     let json = serde_json::to_string(response)?;
     stream.write_all(json.as_bytes())?;
@@ -1,30 +1,333 @@
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::condition::{Condition, Location, MatchResult};
 use crate::error::{Error, Result};
-use crate::input::{self, Key};
-use crate::screen::{self, ScreenSnapshot};
+use crate::input::{self, Key, KeyEvent, KeyModes, KeyOverride, KeyToken, Modifiers, MouseAction, MouseButton, MouseEvent};
+use crate::process::{self, ProcessInfo, TeardownReport};
+use crate::screen::{ScreenSnapshot, TerminalModes, Theme};
+use crate::style::StyleQuery;
+use crate::terminal::{Terminal, TerminalIdentity};
 use crate::trace::TraceRecorder;
+use crate::wait::{Waiters, Watcher};
 
 type TraceSink = Arc<Mutex<Option<TraceRecorder>>>;
+type PtyWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+// Held for the whole of each input request, so a paced sequence cannot have
+// other input land in the middle of it.
+type InputLock = Arc<Mutex<()>>;
+
+#[derive(Debug, Clone)]
+pub struct SpawnOptions {
+    pub identity: TerminalIdentity,
+    pub theme: Theme,
+    pub scrollback: usize,
+    // How long the daemon keeps serving requests after the child exits.
+    pub linger: Duration,
+    // Extra variables, applied last so they override everything below.
+    pub env: Vec<(String, String)>,
+    // Start from an empty environment; PATH is kept so the command resolves.
+    pub env_clear: bool,
+    pub term: Option<String>,
+    pub colorterm: Option<String>,
+    // Sets both LANG and LC_ALL.
+    pub lang: Option<String>,
+    // Clear the environment and use a fixed TERM, LANG and TZ with a fresh,
+    // empty HOME that is removed along with the session.
+    pub hermetic: bool,
+    // How long processes get to exit after SIGTERM before they are SIGKILLed.
+    pub kill_grace: Duration,
+}
+
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        SpawnOptions {
+            identity: TerminalIdentity::default(),
+            theme: Theme::default(),
+            scrollback: 1000,
+            linger: Duration::from_secs(60),
+            env: Vec::new(),
+            env_clear: false,
+            term: None,
+            colorterm: None,
+            lang: None,
+            hermetic: false,
+            kill_grace: Duration::from_secs(2),
+        }
+    }
+}
+
+const HERMETIC_TERM: &str = "xterm-256color";
+const HERMETIC_LANG: &str = "C.UTF-8";
+const HERMETIC_TZ: &str = "UTC";
+
+struct TempHome(PathBuf);
+
+impl TempHome {
+    fn create() -> Result<Self> {
+        use rand::Rng;
+        let name = format!("tui-wright-home-{}-{:08x}", std::process::id(), rand::thread_rng().gen::<u32>());
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path)?;
+        Ok(TempHome(path))
+    }
+}
+
+impl Drop for TempHome {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn build_command(
+    command: &str,
+    args: &[String],
+    cwd: &Path,
+    options: &SpawnOptions,
+) -> Result<(CommandBuilder, Option<TempHome>)> {
+    let mut cmd = CommandBuilder::new(command);
+    cmd.args(args);
+    cmd.cwd(cwd);
+
+    let mut home = None;
+    if options.env_clear || options.hermetic {
+        let path = cmd.get_env("PATH").map(|p| p.to_os_string());
+        cmd.env_clear();
+        if let Some(path) = path {
+            cmd.env("PATH", path);
+        }
+    }
+    if options.hermetic {
+        let dir = TempHome::create()?;
+        cmd.env("HOME", &dir.0);
+        cmd.env("TERM", HERMETIC_TERM);
+        cmd.env("LANG", HERMETIC_LANG);
+        cmd.env("LC_ALL", HERMETIC_LANG);
+        cmd.env("TZ", HERMETIC_TZ);
+        home = Some(dir);
+    }
+    if let Some(term) = &options.term {
+        cmd.env("TERM", term);
+    }
+    if let Some(colorterm) = &options.colorterm {
+        cmd.env("COLORTERM", colorterm);
+    }
+    if let Some(lang) = &options.lang {
+        cmd.env("LANG", lang);
+        cmd.env("LC_ALL", lang);
+    }
+    for (key, value) in &options.env {
+        cmd.env(key, value);
+    }
+    Ok((cmd, home))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExitStatus {
+    pub code: u32,
+    // Description of the signal that terminated the child, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+}
+
+impl ExitStatus {
+    pub fn success(&self) -> bool {
+        self.code == 0 && self.signal.is_none()
+    }
+}
+
+impl From<portable_pty::ExitStatus> for ExitStatus {
+    fn from(status: portable_pty::ExitStatus) -> Self {
+        ExitStatus {
+            code: status.exit_code(),
+            signal: status.signal().map(str::to_string),
+        }
+    }
+}
+
+// Set once by the thread that reaps the child; readable after the child has
+// gone so the daemon can still report how it ended.
+#[derive(Clone, Default)]
+pub struct ExitWatch {
+    state: Arc<(Mutex<Option<ExitStatus>>, Condvar)>,
+}
+
+impl ExitWatch {
+    pub fn status(&self) -> Option<ExitStatus> {
+        self.state.0.lock().unwrap().clone()
+    }
+
+    pub fn wait(&self, timeout: Duration) -> Result<ExitStatus> {
+        let (lock, cond) = &*self.state;
+        let status = lock.lock().unwrap();
+        let (status, _) = cond.wait_timeout_while(status, timeout, |s| s.is_none()).unwrap();
+        status.clone().ok_or(Error::Timeout(timeout.as_millis() as u64))
+    }
+
+    fn set(&self, status: ExitStatus) {
+        let (lock, cond) = &*self.state;
+        *lock.lock().unwrap() = Some(status);
+        cond.notify_all();
+    }
+}
+
+// The session's input side, for writes that pace themselves and so are sent
+// from another thread rather than holding up the daemon. Every write the
+// session makes goes through here; input that arrives while a paced sequence
+// is being sent waits until it is done.
+#[derive(Clone)]
+pub struct Input {
+    terminal: Arc<Mutex<Terminal>>,
+    writer: PtyWriter,
+    trace: TraceSink,
+    lock: InputLock,
+}
+
+impl Input {
+    // One grapheme per write, each traced as its own input event, waiting
+    // `delay` plus up to `jitter` between them.
+    pub fn type_text_paced(&self, text: &str, delay: Duration, jitter: Duration) -> Result<()> {
+        use rand::Rng;
+        let _lock = self.lock.lock().unwrap();
+        for (i, grapheme) in text.graphemes(true).enumerate() {
+            if i > 0 {
+                thread::sleep(delay + rand::thread_rng().gen_range(Duration::ZERO..=jitter));
+            }
+            self.write_locked(grapheme.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Every key is encoded before anything is written, so a sequence with a
+    // key that cannot be encoded sends nothing. With no delay it goes out in a
+    // single write; otherwise each key after the first is encoded again for
+    // the cursor and keypad modes in effect when it is sent.
+    pub fn send_keys(&self, sequence: &str, delay: Duration) -> Result<()> {
+        let tokens = input::parse_key_sequence(sequence)?;
+        let _lock = self.lock.lock().unwrap();
+        let modes = self.key_modes();
+        let encoded = tokens.iter().map(|token| encode_token(token, modes)).collect::<Result<Vec<_>>>()?;
+        if delay.is_zero() {
+            let seq = encoded.concat();
+            if seq.is_empty() {
+                return Ok(());
+            }
+            return self.write_locked(&seq);
+        }
+        for (i, (token, mut seq)) in tokens.iter().zip(encoded).enumerate() {
+            if i > 0 {
+                thread::sleep(delay);
+                if let KeyToken::Key(_) = token {
+                    seq = encode_token(token, self.key_modes())?;
+                }
+            }
+            if !seq.is_empty() {
+                self.write_locked(&seq)?;
+            }
+        }
+        Ok(())
+    }
+
+    // `count` press/release pairs, `interval` apart, so the app sees a double
+    // or triple click.
+    pub fn click(
+        &self,
+        col: u16,
+        row: u16,
+        button: MouseButton,
+        modifiers: Modifiers,
+        count: u32,
+        interval: Duration,
+    ) -> Result<()> {
+        if count == 0 {
+            return Err(Error::InvalidArgument("click count must be at least 1".to_string()));
+        }
+        let event = |action| MouseEvent { action, col, row, modifiers };
+        let _lock = self.lock.lock().unwrap();
+        for i in 0..count {
+            if i > 0 {
+                thread::sleep(interval);
+            }
+            self.write_mouse_events(&[event(MouseAction::Press(button)), event(MouseAction::Release(button))])?;
+        }
+        Ok(())
+    }
+
+    pub fn send_mouse_events(&self, events: &[MouseEvent]) -> Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        self.write_mouse_events(events)
+    }
+
+    pub fn write(&self, bytes: &[u8]) -> Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        self.write_locked(bytes)
+    }
+
+    // Events the app's tracking mode does not cover are dropped.
+    fn write_mouse_events(&self, events: &[MouseEvent]) -> Result<()> {
+        let modes = self.terminal.lock().unwrap().modes();
+        let seq: Vec<u8> = events.iter().flat_map(|event| event.encode_for(&modes)).collect();
+        if seq.is_empty() {
+            return Ok(());
+        }
+        self.write_locked(&seq)
+    }
+
+    fn key_modes(&self) -> KeyModes {
+        KeyModes::from(&self.terminal.lock().unwrap().modes())
+    }
+
+    // The caller holds the input lock.
+    fn write_locked(&self, bytes: &[u8]) -> Result<()> {
+        if let Ok(mut t) = self.trace.lock() {
+            if let Some(ref mut recorder) = *t {
+                let _ = recorder.record_input(bytes);
+            }
+        }
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(bytes)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
 
 pub struct Session {
-    parser: Arc<Mutex<vt100::Parser>>,
-    writer: Box<dyn Write + Send>,
+    terminal: Arc<Mutex<Terminal>>,
+    writer: PtyWriter,
+    input_lock: InputLock,
     pty: portable_pty::PtyPair,
-    child: Box<dyn portable_pty::Child + Send + Sync>,
+    pid: Option<u32>,
+    kill_grace: Duration,
+    torn_down: bool,
+    exit: ExitWatch,
     _reader_handle: thread::JoinHandle<()>,
     trace: TraceSink,
+    waiters: Waiters,
     cols: u16,
     rows: u16,
+    _home: Option<TempHome>,
 }
 
 impl Session {
     pub fn spawn(command: &str, args: &[String], cols: u16, rows: u16, cwd: &Path) -> Result<Self> {
+        Self::spawn_with_options(command, args, cols, rows, cwd, SpawnOptions::default())
+    }
+
+    pub fn spawn_with_options(
+        command: &str,
+        args: &[String],
+        cols: u16,
+        rows: u16,
+        cwd: &Path,
+        options: SpawnOptions,
+    ) -> Result<Self> {
         let pty_system = NativePtySystem::default();
         let pty = pty_system.openpty(PtySize {
             rows,
@@ -33,18 +336,20 @@ impl Session {
             pixel_height: 0,
         })?;
 
-        let mut cmd = CommandBuilder::new(command);
-        cmd.args(args);
-        cmd.cwd(cwd);
-
-        let child = pty.slave.spawn_command(cmd)?;
-        let writer = pty.master.take_writer()?;
+        let (cmd, home) = build_command(command, args, cwd, &options)?;
+        let mut child = pty.slave.spawn_command(cmd)?;
+        let pid = child.process_id();
+        let writer: PtyWriter = Arc::new(Mutex::new(pty.master.take_writer()?));
         let mut reader = pty.master.try_clone_reader()?;
 
-        let parser = Arc::new(Mutex::new(vt100::Parser::new(rows, cols, 0)));
+        let terminal = Terminal::new(rows, cols, options.scrollback, options.identity, options.theme);
+        let terminal = Arc::new(Mutex::new(terminal));
         let trace: TraceSink = Arc::new(Mutex::new(None));
+        let waiters = Waiters::default();
 
-        let parser_clone = Arc::clone(&parser);
+        let waiters_clone = waiters.clone();
+        let terminal_clone = Arc::clone(&terminal);
+        let writer_clone = Arc::clone(&writer);
         let trace_clone = Arc::clone(&trace);
         let reader_handle = thread::spawn(move || {
             let mut buf = [0u8; 4096];
@@ -57,68 +362,236 @@ impl Session {
                                 let _ = recorder.record_output(&buf[..n]);
                             }
                         }
-                        let mut p = parser_clone.lock().unwrap();
-                        p.process(&buf[..n]);
+                        let replies = {
+                            let mut terminal = terminal_clone.lock().unwrap();
+                            let replies = terminal.process(&buf[..n]);
+                            waiters_clone.notify(&mut terminal);
+                            replies
+                        };
+                        if !replies.is_empty() {
+                            if let Ok(mut t) = trace_clone.lock() {
+                                if let Some(ref mut recorder) = *t {
+                                    let _ = recorder.record_input(&replies);
+                                }
+                            }
+                            let mut w = writer_clone.lock().unwrap();
+                            let _ = w.write_all(&replies);
+                            let _ = w.flush();
+                        }
                     }
                     Err(_) => break,
                 }
             }
+            waiters_clone.close();
+        });
+
+        let exit = ExitWatch::default();
+        let exit_clone = exit.clone();
+        let waiters_clone = waiters.clone();
+        thread::spawn(move || {
+            if let Ok(status) = child.wait() {
+                // Give the reader a moment to process the child's last output
+                // so the final screen is in place once the exit is reported.
+                let deadline = Instant::now() + Duration::from_millis(250);
+                while !waiters_clone.is_closed() && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                exit_clone.set(status.into());
+            }
         });
 
         Ok(Session {
-            parser,
+            terminal,
             writer,
+            input_lock: InputLock::default(),
             pty,
-            child,
+            pid,
+            kill_grace: options.kill_grace,
+            torn_down: false,
+            exit,
             _reader_handle: reader_handle,
             trace,
+            waiters,
             cols,
             rows,
+            _home: home,
         })
     }
 
     pub fn screen_text(&self) -> String {
-        let parser = self.parser.lock().unwrap();
-        screen::screen_text(parser.screen())
+        self.terminal.lock().unwrap().screen_text()
     }
 
     pub fn screen_snapshot(&self) -> ScreenSnapshot {
-        let parser = self.parser.lock().unwrap();
-        screen::from_screen(parser.screen())
+        let terminal = self.terminal.lock().unwrap();
+        terminal.snapshot()
+    }
+
+    pub fn modes(&self) -> TerminalModes {
+        self.terminal.lock().unwrap().modes()
+    }
+
+    pub fn scrollback_text(&self) -> String {
+        self.terminal.lock().unwrap().scrollback_text()
+    }
+
+    pub fn scrollback_snapshot(&self) -> ScreenSnapshot {
+        self.terminal.lock().unwrap().scrollback_snapshot()
+    }
+
+    pub fn input(&self) -> Input {
+        Input {
+            terminal: Arc::clone(&self.terminal),
+            writer: Arc::clone(&self.writer),
+            trace: Arc::clone(&self.trace),
+            lock: Arc::clone(&self.input_lock),
+        }
+    }
+
+    pub fn watcher(&self) -> Watcher {
+        Watcher::new(Arc::clone(&self.terminal), self.waiters.clone())
+    }
+
+    pub fn wait_for_text(&self, text: &str, scrollback: bool, timeout: Duration) -> Result<String> {
+        self.watcher().wait_for_text(text, scrollback, timeout)
+    }
+
+    pub fn wait_for(&self, condition: &Condition, timeout: Duration) -> Result<(String, MatchResult)> {
+        self.watcher().wait_for(condition, timeout)
+    }
+
+    // Evaluates the condition against the current screen, returning the text
+    // it was evaluated against alongside the result.
+    pub fn check(&self, condition: &Condition) -> Result<(String, MatchResult)> {
+        let matcher = condition.compile()?;
+        Ok(matcher.check(&mut self.terminal.lock().unwrap()))
+    }
+
+    pub fn find(&self, condition: &Condition) -> Result<Vec<Location>> {
+        let matcher = condition.compile()?;
+        Ok(matcher.locate(&self.screen_snapshot()))
+    }
+
+    pub fn find_style(&self, query: &StyleQuery) -> Vec<Location> {
+        query.spans(&self.screen_snapshot())
+    }
+
+    // Clicks the centre of the `index`th match with the left button.
+    pub fn click_text(&mut self, condition: &Condition, index: usize) -> Result<Location> {
+        let locations = self.find(condition)?;
+        let location = locations
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| Error::NotFound(condition.to_string()))?;
+        let (col, row) = location.center();
+        self.click(col, row, MouseButton::Left, Modifiers::NONE, 1, Duration::ZERO)?;
+        Ok(location)
+    }
+
+    pub fn wait_stable(&self, quiet: Duration, timeout: Duration) -> Result<()> {
+        self.watcher().wait_stable(quiet, timeout)
     }
 
     pub fn cursor_position(&self) -> (u16, u16) {
-        let parser = self.parser.lock().unwrap();
-        parser.screen().cursor_position()
+        let terminal = self.terminal.lock().unwrap();
+        terminal.screen().cursor_position()
     }
 
     pub fn type_text(&mut self, text: &str) -> Result<()> {
-        self.trace_input(text.as_bytes());
-        self.writer.write_all(text.as_bytes())?;
-        self.writer.flush()?;
-        Ok(())
+        self.input().write(text.as_bytes())
+    }
+
+    pub fn type_text_paced(&mut self, text: &str, delay: Duration, jitter: Duration) -> Result<()> {
+        self.input().type_text_paced(text, delay, jitter)
+    }
+
+    // Bracketed when the app has enabled it, or when forced.
+    pub fn paste(&mut self, text: &str, force: bool) -> Result<()> {
+        let bracketed = force || self.modes().bracketed_paste;
+        let seq = input::paste_sequence(text, bracketed);
+        self.input().write(&seq)
     }
 
     pub fn send_key(&mut self, key: &Key) -> Result<()> {
-        let seq = key.to_escape_sequence();
-        self.trace_input(&seq);
-        self.writer.write_all(&seq)?;
-        self.writer.flush()?;
-        Ok(())
+        self.send_key_with(key, KeyEvent::Press, KeyOverride::default())
+    }
+
+    // Encoded the way xterm would given the cursor and keypad modes the app
+    // has currently selected.
+    pub fn send_key_with(&mut self, key: &Key, event: KeyEvent, encoding: KeyOverride) -> Result<()> {
+        let modes = encoding.apply(KeyModes::from(&self.modes()));
+        let seq = key.try_encode(event, modes)?;
+        if seq.is_empty() {
+            return Ok(());
+        }
+        self.input().write(&seq)
     }
 
     pub fn send_key_by_name(&mut self, name: &str) -> Result<()> {
+        self.send_key_by_name_with(name, KeyEvent::Press, KeyOverride::default())
+    }
+
+    pub fn send_key_by_name_with(&mut self, name: &str, event: KeyEvent, encoding: KeyOverride) -> Result<()> {
         let key = input::parse_key_name(name)?;
-        self.send_key(&key)
+        self.send_key_with(&key, event, encoding)
     }
 
-    pub fn send_mouse(&mut self, action: &str, col: u16, row: u16) -> Result<()> {
-        let mouse_action = input::parse_mouse_action(action)?;
-        let seq = input::mouse_sgr_sequence(&mouse_action, col, row);
-        self.trace_input(&seq);
-        self.writer.write_all(&seq)?;
-        self.writer.flush()?;
-        Ok(())
+    pub fn send_keys(&mut self, sequence: &str, delay: Duration) -> Result<()> {
+        self.input().send_keys(sequence, delay)
+    }
+
+    pub fn send_mouse(&mut self, action: &str, col: u16, row: u16, button: MouseButton, modifiers: Modifiers) -> Result<()> {
+        let action = input::parse_mouse_action(action, button)?;
+        self.send_mouse_events(&[MouseEvent { action, col, row, modifiers }])
+    }
+
+    pub fn click(
+        &mut self,
+        col: u16,
+        row: u16,
+        button: MouseButton,
+        modifiers: Modifiers,
+        count: u32,
+        interval: Duration,
+    ) -> Result<()> {
+        self.input().click(col, row, button, modifiers, count, interval)
+    }
+
+    // Only reported if the app has enabled focus events, or when forced, the
+    // same way a terminal drops events the app has not asked for.
+    pub fn focus(&mut self, gained: bool, force: bool) -> Result<()> {
+        if !force && !self.modes().focus_events {
+            return Ok(());
+        }
+        let seq = input::focus_sequence(gained);
+        self.input().write(seq)
+    }
+
+    // Press at `from`, a drag event for each cell on the way and a release at
+    // `to`, written in one go.
+    pub fn drag(&mut self, from: (u16, u16), to: (u16, u16), button: MouseButton, modifiers: Modifiers) -> Result<()> {
+        let event = |action, (col, row): (u16, u16)| MouseEvent { action, col, row, modifiers };
+        let mut events = vec![event(MouseAction::Press(button), from)];
+        events.extend(input::drag_path(from, to).into_iter().map(|point| event(MouseAction::Drag(button), point)));
+        events.push(event(MouseAction::Release(button), to));
+        self.send_mouse_events(&events)
+    }
+
+    pub fn send_mouse_events(&mut self, events: &[MouseEvent]) -> Result<()> {
+        self.input().send_mouse_events(events)
+    }
+
+    // Delivered to the terminal's foreground process group, as if generated
+    // from the keyboard, falling back to the child's own group.
+    pub fn signal(&self, name: &str) -> Result<()> {
+        let signal = process::parse_signal(name)?;
+        let pgrp = self
+            .pty
+            .master
+            .process_group_leader()
+            .or(self.pid.map(|pid| pid as libc::pid_t))
+            .ok_or(Error::ChildExited)?;
+        process::signal_group(pgrp, signal)
     }
 
     pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
@@ -128,8 +601,8 @@ impl Session {
             pixel_width: 0,
             pixel_height: 0,
         })?;
-        let mut parser = self.parser.lock().unwrap();
-        parser.set_size(rows, cols);
+        let mut terminal = self.terminal.lock().unwrap();
+        terminal.set_size(rows, cols);
         if let Ok(mut t) = self.trace.lock() {
             if let Some(ref mut recorder) = *t {
                 let _ = recorder.record_resize(cols, rows);
@@ -139,16 +612,60 @@ impl Session {
     }
 
     pub fn kill(&mut self) -> Result<()> {
-        self.child.kill().map_err(|e| Error::Pty(e.to_string()))?;
+        self.teardown(self.kill_grace);
         Ok(())
     }
 
-    pub fn is_alive(&mut self) -> bool {
-        self.child
-            .try_wait()
-            .ok()
-            .map(|status| status.is_none())
-            .unwrap_or(false)
+    // Terminates the child and everything it started in the terminal, not just
+    // the direct child, so nothing outlives the session. Only the first call
+    // does anything. Once the child has been reaped and nothing of its session
+    // is left, its pid may already belong to an unrelated process, so that is
+    // left alone too.
+    pub fn teardown(&mut self, grace: Duration) -> TeardownReport {
+        let Some(pid) = self.pid else {
+            return TeardownReport::default();
+        };
+        if std::mem::replace(&mut self.torn_down, true) {
+            return TeardownReport::default();
+        }
+        if !self.is_alive() && process::session_processes(pid).is_empty() {
+            return TeardownReport::default();
+        }
+        process::teardown(pid, grace)
+    }
+
+    pub fn kill_grace(&self) -> Duration {
+        self.kill_grace
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.exit.status().is_none()
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    pub fn process_info(&self) -> ProcessInfo {
+        let foreground_pgrp = self.pty.master.process_group_leader().map(|pgrp| pgrp as u32);
+        ProcessInfo {
+            pid: self.pid,
+            foreground_pgrp,
+            foreground_command: foreground_pgrp.and_then(process::command_line),
+            termios: self.pty.master.as_raw_fd().and_then(process::termios),
+        }
+    }
+
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit.status()
+    }
+
+    pub fn exit_watch(&self) -> ExitWatch {
+        self.exit.clone()
+    }
+
+    pub fn wait_exit(&self, timeout: Duration) -> Result<ExitStatus> {
+        self.exit.wait(timeout)
     }
 
     pub fn trace_start(&self, output_path: PathBuf, title: Option<String>) -> Result<()> {
@@ -173,12 +690,19 @@ impl Session {
             }
         }
     }
+}
 
-    fn trace_input(&self, raw_bytes: &[u8]) {
-        if let Ok(mut t) = self.trace.lock() {
-            if let Some(ref mut recorder) = *t {
-                let _ = recorder.record_input(raw_bytes);
-            }
-        }
+fn encode_token(token: &KeyToken, modes: KeyModes) -> Result<Vec<u8>> {
+    match token {
+        KeyToken::Key(key) => key.try_encode(KeyEvent::Press, modes),
+        KeyToken::Text(text) => Ok(text.as_bytes().to_vec()),
+    }
+}
+
+// Dropping a session kills whatever is left of it straight away rather than
+// blocking for the grace period; call `teardown` first to stop it gracefully.
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.teardown(Duration::ZERO);
     }
 }
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+use crate::condition::Location;
+use crate::screen::{CellInfo, ColorInfo, ColorKind, Region, ScreenSnapshot};
+
+// A colour to select cells by. Truecolor matches any cell resolving to the
+// same RGB; "default" or a palette index matches only cells that specified
+// exactly that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorQuery {
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl ColorQuery {
+    // "default", a palette index or #rrggbb.
+    pub fn parse(spec: &str) -> Option<Self> {
+        if spec.eq_ignore_ascii_case("default") {
+            return Some(ColorQuery::Default);
+        }
+        if let Ok(idx) = spec.parse::<u8>() {
+            return Some(ColorQuery::Indexed(idx));
+        }
+        let c = ColorInfo::parse_hex(spec)?;
+        Some(ColorQuery::Rgb(c.r, c.g, c.b))
+    }
+
+    pub fn matches(&self, color: &ColorInfo) -> bool {
+        match *self {
+            ColorQuery::Default => color.kind == ColorKind::Default,
+            ColorQuery::Indexed(idx) => color.kind == ColorKind::Indexed(idx),
+            ColorQuery::Rgb(r, g, b) => (color.r, color.g, color.b) == (r, g, b),
+        }
+    }
+}
+
+// Selects cells by colour and attributes. Unset fields match anything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StyleQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fg: Option<ColorQuery>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bg: Option<ColorQuery>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dim: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underline: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inverse: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blink: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+}
+
+impl StyleQuery {
+    pub fn matches(&self, cell: &CellInfo) -> bool {
+        let flag = |want: Option<bool>, have: bool| want.is_none_or(|want| want == have);
+        self.fg.is_none_or(|c| c.matches(&cell.fg))
+            && self.bg.is_none_or(|c| c.matches(&cell.bg))
+            && flag(self.bold, cell.bold)
+            && flag(self.dim, cell.dim)
+            && flag(self.italic, cell.italic)
+            && flag(self.underline, cell.underline)
+            && flag(self.inverse, cell.inverse)
+            && flag(self.blink, cell.blink)
+            && flag(self.strikethrough, cell.strikethrough)
+    }
+
+    // Maximal runs of matching cells within each row of the region.
+    pub fn spans(&self, snapshot: &ScreenSnapshot) -> Vec<Location> {
+        let region = self.region.unwrap_or(Region::rows(0, u16::MAX));
+        let mut spans = Vec::new();
+        for (row, cells) in snapshot.cells.iter().enumerate() {
+            if !(region.top as usize..=region.bottom as usize).contains(&row) {
+                continue;
+            }
+            let mut current: Option<Location> = None;
+            for (col, cell) in cells.iter().enumerate() {
+                let inside = (region.left as usize..=region.right as usize).contains(&col);
+                if !inside || !self.matches(cell) {
+                    spans.extend(current.take());
+                    continue;
+                }
+                let span = current.get_or_insert_with(|| Location {
+                    row: row as u16,
+                    start_col: col as u16,
+                    end_col: col as u16,
+                    text: String::new(),
+                });
+                span.end_col = col as u16 + 1;
+                if !cell.wide_continuation {
+                    span.text.push_str(if cell.char.is_empty() { " " } else { &cell.char });
+                }
+            }
+            spans.extend(current);
+        }
+        spans
+    }
+
+    pub fn rows(&self, snapshot: &ScreenSnapshot) -> Vec<u16> {
+        let mut rows: Vec<u16> = self.spans(snapshot).iter().map(|span| span.row).collect();
+        rows.dedup();
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen;
+
+    fn menu() -> ScreenSnapshot {
+        let mut parser = vt100::Parser::new(4, 12, 0);
+        parser.process(b"  Open\r\n\x1b[7m> Save    \x1b[m\r\n  \x1b[1;41mQuit\x1b[m");
+        screen::from_screen(parser.screen())
+    }
+
+    #[test]
+    fn test_inverse_rows() {
+        let query = StyleQuery { inverse: Some(true), ..Default::default() };
+        assert_eq!(query.rows(&menu()), vec![1]);
+        let spans = query.spans(&menu());
+        assert_eq!((spans[0].start_col, spans[0].end_col), (0, 10));
+        assert_eq!(spans[0].text.trim(), "> Save");
+    }
+
+    #[test]
+    fn test_background_spans() {
+        let snapshot = menu();
+        let indexed = StyleQuery { bg: Some(ColorQuery::Indexed(1)), ..Default::default() };
+        let spans = indexed.spans(&snapshot);
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].row, spans[0].start_col, spans[0].end_col), (2, 2, 6));
+        assert_eq!(spans[0].text, "Quit");
+
+        let resolved = StyleQuery { bg: Some(ColorQuery::Rgb(205, 0, 0)), bold: Some(true), ..Default::default() };
+        assert_eq!(resolved.spans(&snapshot), spans);
+        let not_bold = StyleQuery { bold: Some(false), ..resolved };
+        assert!(not_bold.spans(&snapshot).is_empty());
+    }
+
+    #[test]
+    fn test_parse_color_query() {
+        assert_eq!(ColorQuery::parse("default"), Some(ColorQuery::Default));
+        assert_eq!(ColorQuery::parse("4"), Some(ColorQuery::Indexed(4)));
+        assert_eq!(ColorQuery::parse("#cd0080"), Some(ColorQuery::Rgb(205, 0, 128)));
+        assert_eq!(ColorQuery::parse("256"), None);
+        assert!(ColorQuery::Default.matches(&ColorInfo::from_vt100_color(vt100::Color::Default)));
+        assert!(!ColorQuery::Indexed(1).matches(&ColorInfo::rgb(205, 0, 0)));
+    }
+
+    #[test]
+    fn test_region_limits_spans() {
+        let query = StyleQuery { inverse: Some(true), region: Some(Region { top: 0, left: 2, bottom: 3, right: 5 }), ..Default::default() };
+        let spans = query.spans(&menu());
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].text, "Save");
+    }
+}
@@ -0,0 +1,889 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use vte::{Params, Perform};
+
+use crate::screen::{self, CellInfo, ColorInfo, MouseEncoding, ScreenSnapshot, TerminalModes, Theme};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerminalIdentity {
+    pub primary_attributes: Vec<u16>,
+    pub secondary_attributes: [u16; 3],
+    pub version: String,
+}
+
+impl Default for TerminalIdentity {
+    fn default() -> Self {
+        TerminalIdentity {
+            primary_attributes: vec![62, 22],
+            secondary_attributes: [1, 95, 0],
+            version: format!("tui-wright({})", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Query {
+    Status,
+    CursorPosition { private: bool },
+    PrimaryAttributes,
+    SecondaryAttributes,
+    Version,
+    DynamicColor { code: u16, bell: bool },
+    PaletteColor { index: u8, bell: bool },
+    KeyboardFlags,
+    ModifyOtherKeys,
+}
+
+// Kitty keyboard enhancement flags are a stack the app pushes to and pops
+// from, kept separately for the main and alternate screens.
+#[derive(Debug, Default)]
+struct KeyboardState {
+    stacks: [Vec<u8>; 2],
+    alternate: bool,
+    modify_other_keys: u8,
+}
+
+const KEYBOARD_STACK_LIMIT: usize = 16;
+
+impl KeyboardState {
+    fn stack(&mut self) -> &mut Vec<u8> {
+        &mut self.stacks[self.alternate as usize]
+    }
+
+    fn flags(&self) -> u8 {
+        self.stacks[self.alternate as usize].last().copied().unwrap_or(0)
+    }
+
+    fn push(&mut self, flags: u8) {
+        let stack = self.stack();
+        if stack.len() == KEYBOARD_STACK_LIMIT {
+            stack.remove(0);
+        }
+        stack.push(flags);
+    }
+
+    fn pop(&mut self, count: usize) {
+        let stack = self.stack();
+        stack.truncate(stack.len().saturating_sub(count));
+    }
+
+    // CSI = flags ; mode u: 1 replaces, 2 sets and 3 clears the given bits.
+    fn set(&mut self, flags: u8, mode: u16) {
+        let current = self.flags();
+        let updated = match mode {
+            2 => current | flags,
+            3 => current & !flags,
+            _ => flags,
+        };
+        match self.stack().last_mut() {
+            Some(top) => *top = updated,
+            None => self.push(updated),
+        }
+    }
+
+    fn set_alternate(&mut self, alternate: bool) {
+        if alternate && !self.alternate {
+            self.stacks[1].clear();
+        }
+        self.alternate = alternate;
+    }
+}
+
+// Cell attributes vt100 does not track itself. They are carried through a
+// shadow parser (see `Scanner::shadow`) so they follow the cells they were
+// written to through scrolling, erasing and alternate screen switches.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ExtraAttrs {
+    blink: bool,
+    strikethrough: bool,
+    hyperlink: u32,
+}
+
+impl ExtraAttrs {
+    fn apply_sgr(&mut self, params: &Params) {
+        let params: Vec<&[u16]> = params.iter().collect();
+        let mut i = 0;
+        while i < params.len() {
+            let param = params[i];
+            match param[0] {
+                0 => {
+                    self.blink = false;
+                    self.strikethrough = false;
+                }
+                5 | 6 => self.blink = true,
+                9 => self.strikethrough = true,
+                25 => self.blink = false,
+                29 => self.strikethrough = false,
+                // Extended colours in semicolon form consume the following parameters.
+                38 | 48 | 58 if param.len() == 1 => match params.get(i + 1).map(|p| p[0]) {
+                    Some(5) => i += 2,
+                    Some(2) => i += 4,
+                    _ => {}
+                },
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    // Encodes the attributes onto the shadow parser's own italic and underline
+    // flags, and the hyperlink id onto its foreground colour.
+    fn shadow_sgr(&self) -> Vec<u8> {
+        let mut sgr = String::from("\x1b[0");
+        if self.blink {
+            sgr.push_str(";3");
+        }
+        if self.strikethrough {
+            sgr.push_str(";4");
+        }
+        if self.hyperlink != 0 {
+            let [_, r, g, b] = self.hyperlink.to_be_bytes();
+            sgr.push_str(&format!(";38;2;{};{};{}", r, g, b));
+        }
+        sgr.push('m');
+        sgr.into_bytes()
+    }
+}
+
+#[derive(Default)]
+struct Scanner {
+    queries: Vec<Query>,
+    attrs: ExtraAttrs,
+    saved_attrs: ExtraAttrs,
+    hyperlinks: Vec<String>,
+    hyperlink_ids: HashMap<String, u32>,
+    // Next id to hand out again once HYPERLINK_LIMIT links have been seen.
+    recycled_hyperlink: usize,
+    shadow: Vec<u8>,
+    keyboard: KeyboardState,
+    // vt100 does not know the urxvt mouse encoding (1015) or focus
+    // reporting (1004).
+    urxvt_mouse: bool,
+    focus_events: bool,
+}
+
+// Distinct link targets remembered at once. Beyond this, ids are reused oldest
+// first, so a cell still carrying a long-replaced link reports the newer one.
+const HYPERLINK_LIMIT: usize = 4096;
+
+impl Scanner {
+    fn set_attrs(&mut self, attrs: ExtraAttrs) {
+        self.attrs = attrs;
+        self.shadow.extend(attrs.shadow_sgr());
+    }
+
+    fn hyperlink_id(&mut self, uri: &str) -> u32 {
+        if let Some(id) = self.hyperlink_ids.get(uri) {
+            return *id;
+        }
+        let id = if self.hyperlinks.len() < HYPERLINK_LIMIT {
+            self.hyperlinks.push(uri.to_string());
+            self.hyperlinks.len() as u32
+        } else {
+            let index = self.recycled_hyperlink;
+            self.recycled_hyperlink = (index + 1) % HYPERLINK_LIMIT;
+            let old = std::mem::replace(&mut self.hyperlinks[index], uri.to_string());
+            self.hyperlink_ids.remove(&old);
+            index as u32 + 1
+        };
+        self.hyperlink_ids.insert(uri.to_string(), id);
+        id
+    }
+
+    fn hyperlink(&self, id: u32) -> Option<&str> {
+        let index = (id as usize).checked_sub(1)?;
+        self.hyperlinks.get(index).map(String::as_str)
+    }
+
+    fn apply_extra_attrs(&self, shadow: &vt100::Screen, row: u16, cells: &mut [CellInfo]) {
+        for (col, cell) in cells.iter_mut().enumerate() {
+            let extra = match shadow.cell(row, col as u16) {
+                Some(extra) => extra,
+                None => continue,
+            };
+            cell.blink = extra.italic();
+            cell.strikethrough = extra.underline();
+            if let vt100::Color::Rgb(r, g, b) = extra.fgcolor() {
+                if extra.has_contents() || extra.is_wide_continuation() {
+                    let id = u32::from_be_bytes([0, r, g, b]);
+                    cell.hyperlink = self.hyperlink(id).map(str::to_string);
+                }
+            }
+        }
+    }
+}
+
+impl Scanner {
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            1049 => {
+                if enabled {
+                    self.saved_attrs = self.attrs;
+                } else {
+                    self.attrs = self.saved_attrs;
+                }
+                self.keyboard.set_alternate(enabled);
+            }
+            47 | 1047 => self.keyboard.set_alternate(enabled),
+            1004 => self.focus_events = enabled,
+            1015 => self.urxvt_mouse = enabled,
+            _ => {}
+        }
+    }
+}
+
+impl Perform for Scanner {
+    // Stops the parser after a query so it can be answered before the rest of
+    // the output is applied to the screen.
+    fn terminated(&self) -> bool {
+        !self.queries.is_empty()
+    }
+
+    fn print(&mut self, c: char) {
+        let mut buf = [0u8; 4];
+        self.shadow.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+
+    fn execute(&mut self, byte: u8) {
+        self.shadow.push(byte);
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if ignore {
+            return;
+        }
+        self.shadow.push(0x1b);
+        self.shadow.extend_from_slice(intermediates);
+        self.shadow.push(byte);
+        match (intermediates, byte) {
+            ([], b'7') => self.saved_attrs = self.attrs,
+            ([], b'8') => self.attrs = self.saved_attrs,
+            // RIS: a full reset drops whatever keyboard protocol, mouse
+            // encoding and focus reporting the app set up.
+            ([], b'c') => {
+                self.keyboard = KeyboardState::default();
+                self.urxvt_mouse = false;
+                self.focus_events = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+        if intermediates.is_empty() && action == 'm' {
+            let mut attrs = self.attrs;
+            attrs.apply_sgr(params);
+            self.set_attrs(attrs);
+            return;
+        }
+        self.shadow.extend(encode_csi(params, intermediates, action));
+
+        let first = params.iter().next().map(|p| p[0]).unwrap_or(0);
+        let second = params.iter().nth(1).map(|p| p[0]);
+        let query = match (intermediates, action, first) {
+            ([], 'n', 5) => Query::Status,
+            ([], 'n', 6) => Query::CursorPosition { private: false },
+            ([b'?'], 'n', 6) => Query::CursorPosition { private: true },
+            ([], 'c', 0) => Query::PrimaryAttributes,
+            ([b'>'], 'c', 0) => Query::SecondaryAttributes,
+            ([b'>'], 'q', 0) => Query::Version,
+            ([b'?'], 'u', _) => Query::KeyboardFlags,
+            ([b'?'], 'm', 4) => Query::ModifyOtherKeys,
+            ([b'>'], 'u', flags) => {
+                self.keyboard.push(flags as u8);
+                return;
+            }
+            ([b'<'], 'u', count) => {
+                self.keyboard.pop(count.max(1) as usize);
+                return;
+            }
+            ([b'='], 'u', flags) => {
+                self.keyboard.set(flags as u8, second.unwrap_or(1));
+                return;
+            }
+            ([b'>'], 'm', 4) => {
+                self.keyboard.modify_other_keys = second.unwrap_or(0) as u8;
+                return;
+            }
+            ([b'?'], 'h' | 'l', _) => {
+                for mode in params.iter() {
+                    self.set_private_mode(mode[0], action == 'h');
+                }
+                return;
+            }
+            _ => return,
+        };
+        self.queries.push(query);
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        let code = match params.first().and_then(|p| parse_number(p)) {
+            Some(code) => code,
+            None => return,
+        };
+        match code {
+            4 => {
+                for pair in params[1..].chunks(2) {
+                    if let [index, b"?"] = pair {
+                        if let Some(index) = parse_number(index).and_then(|i| u8::try_from(i).ok()) {
+                            self.queries.push(Query::PaletteColor { index, bell: bell_terminated });
+                        }
+                    }
+                }
+            }
+            // OSC 8 ; params ; URI -- an empty URI ends the hyperlink. The URI
+            // itself may contain semicolons, which vte splits on.
+            8 if params.len() >= 3 => {
+                let uri = params[2..].join(&b';');
+                let uri = String::from_utf8_lossy(&uri);
+                let mut attrs = self.attrs;
+                attrs.hyperlink = if uri.is_empty() { 0 } else { self.hyperlink_id(&uri) };
+                self.set_attrs(attrs);
+            }
+            // A single OSC 10 may query several consecutive dynamic colours.
+            10..=12 => {
+                for (offset, param) in params[1..].iter().enumerate() {
+                    let code = code + offset as u16;
+                    if *param == b"?" && code <= 12 {
+                        self.queries.push(Query::DynamicColor { code, bell: bell_terminated });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn encode_csi(params: &Params, intermediates: &[u8], action: char) -> Vec<u8> {
+    let (private, intermediates): (Vec<u8>, Vec<u8>) =
+        intermediates.iter().partition(|b| (0x3c..=0x3f).contains(*b));
+    let params: Vec<String> = params
+        .iter()
+        .map(|p| p.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(":"))
+        .collect();
+    let mut seq = b"\x1b[".to_vec();
+    seq.extend(private);
+    seq.extend(params.join(";").into_bytes());
+    seq.extend(intermediates);
+    seq.push(action as u8);
+    seq
+}
+
+fn parse_number(bytes: &[u8]) -> Option<u16> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+pub struct Terminal {
+    parser: vt100::Parser,
+    shadow: vt100::Parser,
+    scanner: vte::Parser,
+    state: Scanner,
+    identity: TerminalIdentity,
+    theme: Theme,
+    // Scrollback text as of the last chunk, so every waiter searching the
+    // history after a chunk shares one rebuild of it.
+    history: Option<String>,
+    last_output: Instant,
+}
+
+impl Terminal {
+    pub fn new(rows: u16, cols: u16, scrollback: usize, identity: TerminalIdentity, theme: Theme) -> Self {
+        Terminal {
+            parser: vt100::Parser::new(rows, cols, scrollback),
+            shadow: vt100::Parser::new(rows, cols, scrollback),
+            scanner: vte::Parser::new(),
+            state: Scanner::default(),
+            identity,
+            theme,
+            history: None,
+            last_output: Instant::now(),
+        }
+    }
+
+    // Feeds output from the child into the emulator and returns the bytes the
+    // terminal would have sent back in response to any queries it contained.
+    // The vt100 parser is only advanced up to the end of each query before it
+    // is answered, so replies see the screen state the application expects.
+    pub fn process(&mut self, bytes: &[u8]) -> Vec<u8> {
+        self.last_output = Instant::now();
+        let mut replies = Vec::new();
+        let mut processed = 0;
+        while processed < bytes.len() {
+            let n = self.scanner.advance_until_terminated(&mut self.state, &bytes[processed..]);
+            self.parser.process(&bytes[processed..processed + n]);
+            processed += n;
+            for query in std::mem::take(&mut self.state.queries) {
+                replies.extend(self.reply(&query));
+            }
+        }
+        self.shadow.process(&std::mem::take(&mut self.state.shadow));
+        self.history = None;
+        replies
+    }
+
+    pub fn screen(&self) -> &vt100::Screen {
+        self.parser.screen()
+    }
+
+    // Time since the child last produced any output.
+    pub fn idle_time(&self) -> Duration {
+        self.last_output.elapsed()
+    }
+
+    pub fn screen_text(&self) -> String {
+        screen::screen_text(self.parser.screen())
+    }
+
+    // Visible screen text, preceded by the scrollback history if requested.
+    pub fn search_text(&mut self, scrollback: bool) -> String {
+        let screen = self.screen_text();
+        if !scrollback {
+            return screen;
+        }
+        let history = self.scrollback_text();
+        if history.is_empty() {
+            screen
+        } else {
+            format!("{}\n{}", history, screen)
+        }
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        let mut snapshot = screen::from_screen_with_theme(self.parser.screen(), &self.theme);
+        snapshot.modes = self.modes();
+        for (row, cells) in snapshot.cells.iter_mut().enumerate() {
+            self.state.apply_extra_attrs(self.shadow.screen(), row as u16, cells);
+        }
+        snapshot
+    }
+
+    pub fn modes(&self) -> TerminalModes {
+        let mut modes = TerminalModes::from_screen(self.parser.screen());
+        // SGR wins when an app enables both, as most terminals prefer it.
+        if self.state.urxvt_mouse && modes.mouse_encoding != MouseEncoding::Sgr {
+            modes.mouse_encoding = MouseEncoding::Urxvt;
+        }
+        modes.focus_events = self.state.focus_events;
+        modes.keyboard_flags = self.state.keyboard.flags();
+        modes.modify_other_keys = self.state.keyboard.modify_other_keys;
+        modes
+    }
+
+    pub fn scrollback_text(&mut self) -> String {
+        if let Some(history) = &self.history {
+            return history.clone();
+        }
+        let mut lines = Vec::new();
+        each_scrollback_row(&mut self.parser, &mut self.shadow, |screen, _, row| {
+            lines.push(screen::row_text(screen, row));
+        });
+        let history = lines.join("\n");
+        self.history = Some(history.clone());
+        history
+    }
+
+    pub fn scrollback_snapshot(&mut self) -> ScreenSnapshot {
+        let cols = self.parser.screen().size().1;
+        let mut cells = Vec::new();
+        each_scrollback_row(&mut self.parser, &mut self.shadow, |screen, shadow, row| {
+            let mut row_cells = screen::row_cells(screen, row, &self.theme);
+            self.state.apply_extra_attrs(shadow, row, &mut row_cells);
+            cells.push(row_cells);
+        });
+        ScreenSnapshot {
+            rows: cells.len() as u16,
+            cols,
+            cursor_row: 0,
+            cursor_col: 0,
+            cells,
+            modes: self.modes(),
+        }
+    }
+
+    pub fn set_size(&mut self, rows: u16, cols: u16) {
+        self.parser.screen_mut().set_size(rows, cols);
+        self.shadow.screen_mut().set_size(rows, cols);
+        self.history = None;
+    }
+
+    fn reply(&self, query: &Query) -> Vec<u8> {
+        match query {
+            Query::Status => b"\x1b[0n".to_vec(),
+            Query::CursorPosition { private } => {
+                let screen = self.parser.screen();
+                let (row, col) = screen.cursor_position();
+                let col = col.min(screen.size().1.saturating_sub(1));
+                let marker = if *private { "?" } else { "" };
+                format!("\x1b[{}{};{}R", marker, row + 1, col + 1).into_bytes()
+            }
+            Query::PrimaryAttributes => {
+                let attrs: Vec<String> = self
+                    .identity
+                    .primary_attributes
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect();
+                format!("\x1b[?{}c", attrs.join(";")).into_bytes()
+            }
+            Query::SecondaryAttributes => {
+                let [kind, version, rom] = self.identity.secondary_attributes;
+                format!("\x1b[>{};{};{}c", kind, version, rom).into_bytes()
+            }
+            Query::Version => format!("\x1bP>|{}\x1b\\", self.identity.version).into_bytes(),
+            Query::DynamicColor { code, bell } => {
+                let color = match code {
+                    10 => &self.theme.foreground,
+                    11 => &self.theme.background,
+                    _ => &self.theme.cursor,
+                };
+                osc_color_reply(&code.to_string(), color, *bell)
+            }
+            Query::PaletteColor { index, bell } => {
+                let color = self.theme.palette_color(*index);
+                osc_color_reply(&format!("4;{}", index), &color, *bell)
+            }
+            Query::KeyboardFlags => format!("\x1b[?{}u", self.state.keyboard.flags()).into_bytes(),
+            Query::ModifyOtherKeys => format!("\x1b[>4;{}m", self.state.keyboard.modify_other_keys).into_bytes(),
+        }
+    }
+}
+
+// vt100 only exposes history by scrolling the viewport back, so walk it a page
+// at a time, oldest line first, and leave it at the bottom again.
+fn each_scrollback_row<F>(parser: &mut vt100::Parser, shadow: &mut vt100::Parser, mut f: F)
+where
+    F: FnMut(&vt100::Screen, &vt100::Screen, u16),
+{
+    let rows = parser.screen().size().0 as usize;
+    parser.screen_mut().set_scrollback(usize::MAX);
+    let mut offset = parser.screen().scrollback();
+    while offset > 0 {
+        parser.screen_mut().set_scrollback(offset);
+        shadow.screen_mut().set_scrollback(offset);
+        let take = offset.min(rows);
+        for row in 0..take as u16 {
+            f(parser.screen(), shadow.screen(), row);
+        }
+        offset -= take;
+    }
+    parser.screen_mut().set_scrollback(0);
+    shadow.screen_mut().set_scrollback(0);
+}
+
+fn osc_color_reply(prefix: &str, color: &ColorInfo, bell: bool) -> Vec<u8> {
+    let terminator = if bell { "\x07" } else { "\x1b\\" };
+    format!("\x1b]{};{}{}", prefix, color.to_x11_spec(), terminator).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terminal() -> Terminal {
+        Terminal::new(24, 80, 0, TerminalIdentity::default(), Theme::default())
+    }
+
+    #[test]
+    fn test_plain_output_has_no_reply() {
+        let mut term = terminal();
+        assert!(term.process(b"hello\r\nworld").is_empty());
+        assert!(term.screen().contents().starts_with("hello\nworld"));
+    }
+
+    #[test]
+    fn test_cursor_position_report() {
+        let mut term = terminal();
+        let reply = term.process(b"\x1b[5;10Habc\x1b[6n");
+        assert_eq!(reply, b"\x1b[5;13R".to_vec());
+    }
+
+    #[test]
+    fn test_cursor_position_uses_position_at_query() {
+        let mut term = terminal();
+        let reply = term.process(b"ab\x1b[6nmore text");
+        assert_eq!(reply, b"\x1b[1;3R".to_vec());
+        assert_eq!(term.screen().cursor_position(), (0, 11));
+    }
+
+    #[test]
+    fn test_query_split_across_chunks() {
+        let mut term = terminal();
+        assert!(term.process(b"x\x1b[").is_empty());
+        assert_eq!(term.process(b"6n"), b"\x1b[1;2R".to_vec());
+    }
+
+    #[test]
+    fn test_device_status_and_private_cpr() {
+        let mut term = terminal();
+        assert_eq!(term.process(b"\x1b[5n"), b"\x1b[0n".to_vec());
+        assert_eq!(term.process(b"\x1b[?6n"), b"\x1b[?1;1R".to_vec());
+    }
+
+    #[test]
+    fn test_device_attributes() {
+        let mut term = terminal();
+        assert_eq!(term.process(b"\x1b[c"), b"\x1b[?62;22c".to_vec());
+        assert_eq!(term.process(b"\x1b[0c"), b"\x1b[?62;22c".to_vec());
+        assert_eq!(term.process(b"\x1b[>c"), b"\x1b[>1;95;0c".to_vec());
+    }
+
+    #[test]
+    fn test_custom_identity() {
+        let identity = TerminalIdentity {
+            primary_attributes: vec![65, 1, 4],
+            secondary_attributes: [41, 390, 0],
+            version: "XTerm(390)".to_string(),
+        };
+        let mut term = Terminal::new(24, 80, 0, identity, Theme::default());
+        assert_eq!(term.process(b"\x1b[c"), b"\x1b[?65;1;4c".to_vec());
+        assert_eq!(term.process(b"\x1b[>0c"), b"\x1b[>41;390;0c".to_vec());
+        assert_eq!(term.process(b"\x1b[>q"), b"\x1bP>|XTerm(390)\x1b\\".to_vec());
+    }
+
+    #[test]
+    fn test_multiple_queries_in_one_chunk() {
+        let mut term = terminal();
+        let reply = term.process(b"\x1b[6n\x1b[c");
+        assert_eq!(reply, b"\x1b[1;1R\x1b[?62;22c".to_vec());
+    }
+
+    #[test]
+    fn test_default_color_queries() {
+        let mut term = terminal();
+        assert_eq!(term.process(b"\x1b]10;?\x07"), b"\x1b]10;rgb:ffff/ffff/ffff\x07".to_vec());
+        assert_eq!(term.process(b"\x1b]11;?\x1b\\"), b"\x1b]11;rgb:0000/0000/0000\x1b\\".to_vec());
+    }
+
+    #[test]
+    fn test_light_theme_background() {
+        let mut term = Terminal::new(24, 80, 0, TerminalIdentity::default(), Theme::light());
+        assert_eq!(term.process(b"\x1b]11;?\x07"), b"\x1b]11;rgb:ffff/ffff/ffff\x07".to_vec());
+        assert_eq!(term.process(b"\x1b]12;?\x07"), b"\x1b]12;rgb:0000/0000/0000\x07".to_vec());
+    }
+
+    #[test]
+    fn test_chained_dynamic_color_query() {
+        let mut term = terminal();
+        let reply = term.process(b"\x1b]10;?;?\x07");
+        assert_eq!(
+            reply,
+            b"\x1b]10;rgb:ffff/ffff/ffff\x07\x1b]11;rgb:0000/0000/0000\x07".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_palette_queries() {
+        let mut theme = Theme::default();
+        theme.palette[4] = ColorInfo::rgb(0x12, 0x34, 0x56);
+        let mut term = Terminal::new(24, 80, 0, TerminalIdentity::default(), theme);
+        let reply = term.process(b"\x1b]4;1;?;4;?\x07");
+        assert_eq!(
+            reply,
+            b"\x1b]4;1;rgb:cdcd/0000/0000\x07\x1b]4;4;rgb:1212/3434/5656\x07".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_palette_set_is_not_answered() {
+        let mut term = terminal();
+        assert!(term.process(b"\x1b]4;1;rgb:ff/00/00\x07").is_empty());
+    }
+
+    #[test]
+    fn test_kitty_keyboard_flag_stack() {
+        let mut term = terminal();
+        assert_eq!(term.process(b"\x1b[?u"), b"\x1b[?0u".to_vec());
+        term.process(b"\x1b[>1u\x1b[>11u");
+        assert_eq!(term.modes().keyboard_flags, 11);
+        assert_eq!(term.process(b"\x1b[?u"), b"\x1b[?11u".to_vec());
+        term.process(b"\x1b[<u");
+        assert_eq!(term.modes().keyboard_flags, 1);
+        term.process(b"\x1b[=2;2u");
+        assert_eq!(term.modes().keyboard_flags, 3);
+        term.process(b"\x1b[=1;3u");
+        assert_eq!(term.modes().keyboard_flags, 2);
+        term.process(b"\x1b[<5u");
+        assert_eq!(term.modes().keyboard_flags, 0);
+    }
+
+    #[test]
+    fn test_kitty_keyboard_alternate_screen() {
+        let mut term = terminal();
+        term.process(b"\x1b[>1u\x1b[?1049h");
+        assert_eq!(term.modes().keyboard_flags, 0);
+        term.process(b"\x1b[>31u");
+        assert_eq!(term.snapshot().modes.keyboard_flags, 31);
+        term.process(b"\x1b[?1049l");
+        assert_eq!(term.modes().keyboard_flags, 1);
+    }
+
+    #[test]
+    fn test_full_reset_clears_keyboard_state() {
+        let mut term = terminal();
+        term.process(b"\x1b[>4;2m\x1b[>11u\x1b[?1049h\x1b[>1u");
+        term.process(b"\x1bc");
+        let modes = term.modes();
+        assert_eq!((modes.keyboard_flags, modes.modify_other_keys), (0, 0));
+        term.process(b"\x1b[?1049l");
+        assert_eq!(term.modes().keyboard_flags, 0);
+    }
+
+    #[test]
+    fn test_modify_other_keys() {
+        let mut term = terminal();
+        term.process(b"\x1b[>4;2m");
+        assert_eq!(term.modes().modify_other_keys, 2);
+        assert_eq!(term.process(b"\x1b[?4m"), b"\x1b[>4;2m".to_vec());
+        term.process(b"\x1b[>4m");
+        assert_eq!(term.modes().modify_other_keys, 0);
+        // Plain SGR is unaffected.
+        term.process(b"\x1b[4mx");
+        assert!(term.snapshot().cells[0][0].underline);
+    }
+
+    #[test]
+    fn test_urxvt_mouse_encoding() {
+        let mut term = terminal();
+        term.process(b"\x1b[?1000;1015h");
+        assert_eq!(term.modes().mouse_encoding, MouseEncoding::Urxvt);
+        term.process(b"\x1b[?1006h");
+        assert_eq!(term.modes().mouse_encoding, MouseEncoding::Sgr);
+        term.process(b"\x1b[?1006l\x1b[?1015l");
+        assert_eq!(term.modes().mouse_encoding, MouseEncoding::Default);
+        term.process(b"\x1b[?1015h\x1bc");
+        assert_eq!(term.modes().mouse_encoding, MouseEncoding::Default);
+    }
+
+    #[test]
+    fn test_focus_events_mode() {
+        let mut term = terminal();
+        assert!(!term.modes().focus_events);
+        term.process(b"\x1b[?25;1004h");
+        assert!(term.modes().focus_events);
+        term.process(b"\x1b[?1004l");
+        assert!(!term.modes().focus_events);
+        term.process(b"\x1b[?1004h\x1bc");
+        assert!(!term.modes().focus_events);
+    }
+
+    #[test]
+    fn test_extra_attributes() {
+        let mut term = terminal();
+        term.process(b"\x1b[2ma\x1b[22;5mb\x1b[0;9mc\x1b[29md");
+        let snap = term.snapshot();
+        let row = &snap.cells[0];
+        assert!(row[0].dim && !row[0].blink && !row[0].strikethrough);
+        assert!(!row[1].dim && row[1].blink);
+        assert!(!row[2].blink && row[2].strikethrough);
+        assert!(!row[3].strikethrough);
+    }
+
+    #[test]
+    fn test_extended_colors_do_not_set_attributes() {
+        let mut term = terminal();
+        term.process(b"\x1b[38;5;2;48;2;9;5;2mx");
+        let cell = &term.snapshot().cells[0][0];
+        assert!(!cell.dim && !cell.blink && !cell.strikethrough);
+        assert_eq!(cell.fg.kind, crate::screen::ColorKind::Indexed(2));
+    }
+
+    #[test]
+    fn test_attributes_follow_scrolling() {
+        let mut term = Terminal::new(3, 10, 0, TerminalIdentity::default(), Theme::default());
+        term.process(b"\x1b[9mstruck\x1b[0m\r\nplain\r\nthird\r\nfourth");
+        let snap = term.snapshot();
+        assert_eq!(snap.cells[0][0].char, "p");
+        assert!(!snap.cells[0][0].strikethrough);
+        term.process(b"\x1b[H\x1b[1L\x1b[9mnew");
+        let snap = term.snapshot();
+        assert!(snap.cells[0][0].strikethrough);
+        assert!(!snap.cells[1][0].strikethrough);
+    }
+
+    #[test]
+    fn test_hyperlinks() {
+        let mut term = terminal();
+        term.process(b"\x1b]8;;https://example.com/a;b\x1b\\link\x1b]8;;\x1b\\ text \x1b]8;id=x;file:///tmp\x07f\x1b]8;;\x07");
+        let snap = term.snapshot();
+        let row = &snap.cells[0];
+        assert_eq!(row[0].hyperlink.as_deref(), Some("https://example.com/a;b"));
+        assert_eq!(row[3].hyperlink.as_deref(), Some("https://example.com/a;b"));
+        assert_eq!(row[4].hyperlink, None);
+        assert_eq!(row[10].hyperlink.as_deref(), Some("file:///tmp"));
+        assert_eq!(row[11].hyperlink, None);
+    }
+
+    #[test]
+    fn test_hyperlink_ids_are_bounded() {
+        let mut term = terminal();
+        for i in 0..HYPERLINK_LIMIT + 10 {
+            term.process(format!("\x1b]8;;https://example.com/{}\x07x\x1b]8;;\x07\r", i).as_bytes());
+        }
+        term.process(b"\x1b]8;;https://example.com/0\x07y\x1b]8;;\x07");
+        assert_eq!(term.state.hyperlinks.len(), HYPERLINK_LIMIT);
+        assert_eq!(term.state.hyperlink_ids.len(), HYPERLINK_LIMIT);
+        let snap = term.snapshot();
+        assert_eq!(snap.cells[0][0].hyperlink.as_deref(), Some("https://example.com/0"));
+    }
+
+    #[test]
+    fn test_wide_characters() {
+        let mut term = terminal();
+        term.process("a\u{4e2d}b".as_bytes());
+        let snap = term.snapshot();
+        let row = &snap.cells[0];
+        assert!(!row[0].wide);
+        assert!(row[1].wide);
+        assert_eq!(row[1].char, "\u{4e2d}");
+        assert!(row[2].wide_continuation);
+        assert_eq!(row[3].char, "b");
+    }
+
+    #[test]
+    fn test_scrollback_beyond_one_screen() {
+        let mut term = Terminal::new(3, 20, 100, TerminalIdentity::default(), Theme::default());
+        let output: String = (1..=10).map(|i| format!("line {}\r\n", i)).collect();
+        term.process(output.as_bytes());
+        let history = term.scrollback_text();
+        let expected: Vec<String> = (1..=8).map(|i| format!("line {}", i)).collect();
+        assert_eq!(history, expected.join("\n"));
+        assert_eq!(term.screen().scrollback(), 0);
+        assert!(term.screen().contents().starts_with("line 9\nline 10"));
+    }
+
+    #[test]
+    fn test_scrollback_text_follows_new_output() {
+        let mut term = Terminal::new(2, 20, 100, TerminalIdentity::default(), Theme::default());
+        term.process(b"one\r\ntwo\r\n");
+        assert_eq!(term.scrollback_text(), "one");
+        term.process(b"three\r\n");
+        assert_eq!(term.scrollback_text(), "one\ntwo");
+    }
+
+    #[test]
+    fn test_scrollback_snapshot_keeps_attributes() {
+        let mut term = Terminal::new(2, 10, 100, TerminalIdentity::default(), Theme::default());
+        term.process(b"\x1b[9;31mold\x1b[0m\r\nx\r\ny");
+        let snap = term.scrollback_snapshot();
+        assert_eq!(snap.rows, 1);
+        assert_eq!(snap.cells[0][0].char, "o");
+        assert!(snap.cells[0][0].strikethrough);
+        assert_eq!(snap.cells[0][0].fg.kind, crate::screen::ColorKind::Indexed(1));
+    }
+
+    #[test]
+    fn test_no_scrollback_configured() {
+        let mut term = Terminal::new(2, 10, 0, TerminalIdentity::default(), Theme::default());
+        term.process(b"a\r\nb\r\nc");
+        assert_eq!(term.scrollback_text(), "");
+        assert_eq!(term.scrollback_snapshot().rows, 0);
+    }
+}
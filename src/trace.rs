@@ -40,7 +40,7 @@ impl TraceRecorder {
         };
 
         let header_json = serde_json::to_string(&header)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
         writeln!(writer, "{}", header_json)?;
         writer.flush()?;
 
@@ -56,7 +56,7 @@ impl TraceRecorder {
 
     fn write_event(&mut self, code: &str, data: &str) -> std::io::Result<()> {
         let event = serde_json::to_string(&(self.elapsed_seconds(), code, data))
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
         writeln!(self.writer, "{}", event)?;
         self.writer.flush()?;
         Ok(())
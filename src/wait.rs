@@ -0,0 +1,295 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::condition::{Condition, MatchResult};
+use crate::error::{Error, Result};
+use crate::terminal::Terminal;
+
+// A registered waiter, evaluated against the terminal after every chunk of
+// output. Returns true once satisfied, having handed its value to the waiting
+// thread; dropping it without that wakes the thread with `ChildExited`.
+type Check = Box<dyn FnMut(&mut Terminal) -> bool + Send>;
+
+#[derive(Default)]
+struct WaitList {
+    waiters: Vec<(u64, Check)>,
+    next_id: u64,
+    closed: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct Waiters {
+    list: Arc<Mutex<WaitList>>,
+}
+
+impl Waiters {
+    // Called by the PTY reader thread, with the terminal still locked, after
+    // each chunk of output has been processed.
+    pub fn notify(&self, terminal: &mut Terminal) {
+        let mut list = self.list.lock().unwrap();
+        list.waiters.retain_mut(|(_, check)| !check(terminal));
+    }
+
+    fn remove(&self, id: u64) {
+        self.list.lock().unwrap().waiters.retain(|(waiter, _)| *waiter != id);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.list.lock().unwrap().closed
+    }
+
+    // Called once the child's output is closed; pending waiters fail fast.
+    pub fn close(&self) {
+        let mut list = self.list.lock().unwrap();
+        list.closed = true;
+        list.waiters.clear();
+    }
+}
+
+#[derive(Clone)]
+pub struct Watcher {
+    terminal: Arc<Mutex<Terminal>>,
+    waiters: Waiters,
+}
+
+impl Watcher {
+    pub fn new(terminal: Arc<Mutex<Terminal>>, waiters: Waiters) -> Self {
+        Watcher { terminal, waiters }
+    }
+
+    pub fn wait_until<T, F>(&self, mut check: F, timeout: Duration) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnMut(&mut Terminal) -> Option<T> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let id = {
+            // Holding the terminal lock while checking and registering means
+            // no output can be processed in between and slip past the waiter.
+            let mut terminal = self.terminal.lock().unwrap();
+            if let Some(value) = check(&mut terminal) {
+                return Ok(value);
+            }
+            let mut list = self.waiters.list.lock().unwrap();
+            if list.closed {
+                return Err(Error::ChildExited);
+            }
+            let id = list.next_id;
+            list.next_id += 1;
+            let waiter: Check = Box::new(move |terminal| match check(terminal) {
+                Some(value) => {
+                    let _ = tx.send(value);
+                    true
+                }
+                None => false,
+            });
+            list.waiters.push((id, waiter));
+            id
+        };
+
+        match rx.recv_timeout(timeout) {
+            Ok(value) => Ok(value),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::ChildExited),
+            Err(RecvTimeoutError::Timeout) => {
+                // Unregister so the check is not re-run on every later chunk,
+                // keeping a match that landed just before it was removed.
+                self.waiters.remove(id);
+                rx.try_recv().map_err(|_| Error::Timeout(timeout.as_millis() as u64))
+            }
+        }
+    }
+
+    // Returns once no output has arrived for `quiet`, i.e. the application has
+    // finished redrawing. The quiet period counts from the later of the last
+    // output and the start of the wait, so an app that has not yet reacted to
+    // input sent just before is not taken to be stable.
+    pub fn wait_stable(&self, quiet: Duration, timeout: Duration) -> Result<()> {
+        let started = Instant::now();
+        let deadline = started + timeout;
+        loop {
+            let idle = self.terminal.lock().unwrap().idle_time().min(started.elapsed());
+            if idle >= quiet {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout(timeout.as_millis() as u64));
+            }
+            thread::sleep((quiet - idle).min(deadline - now));
+        }
+    }
+
+    pub fn wait_for_text(&self, text: &str, scrollback: bool, timeout: Duration) -> Result<String> {
+        let text = text.to_string();
+        self.wait_until(
+            move |terminal| {
+                let haystack = terminal.search_text(scrollback);
+                haystack.contains(&text).then(|| terminal.screen_text())
+            },
+            timeout,
+        )
+    }
+
+    // Returns the text that satisfied the condition along with the match.
+    pub fn wait_for(&self, condition: &Condition, timeout: Duration) -> Result<(String, MatchResult)> {
+        let matcher = condition.compile()?;
+        self.wait_until(
+            move |terminal| {
+                let (text, result) = matcher.check(terminal);
+                result.matched.then_some((text, result))
+            },
+            timeout,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::Theme;
+    use crate::terminal::TerminalIdentity;
+
+    fn watcher() -> (Watcher, Arc<Mutex<Terminal>>, Waiters) {
+        let terminal = Terminal::new(4, 20, 100, TerminalIdentity::default(), Theme::default());
+        let terminal = Arc::new(Mutex::new(terminal));
+        let waiters = Waiters::default();
+        (Watcher::new(Arc::clone(&terminal), waiters.clone()), terminal, waiters)
+    }
+
+    fn feed(terminal: &Arc<Mutex<Terminal>>, waiters: &Waiters, bytes: &[u8]) {
+        let mut terminal = terminal.lock().unwrap();
+        terminal.process(bytes);
+        waiters.notify(&mut terminal);
+    }
+
+    #[test]
+    fn test_already_satisfied() {
+        let (watcher, terminal, waiters) = watcher();
+        feed(&terminal, &waiters, b"ready");
+        let screen = watcher.wait_for_text("ready", false, Duration::from_millis(10)).unwrap();
+        assert_eq!(screen, "ready");
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (watcher, _, _) = watcher();
+        let result = watcher.wait_for_text("never", false, Duration::from_millis(20));
+        assert!(matches!(result, Err(Error::Timeout(20))));
+    }
+
+    #[test]
+    fn test_timed_out_waiter_is_removed() {
+        let (watcher, terminal, waiters) = watcher();
+        assert!(watcher.wait_for_text("never", true, Duration::from_millis(20)).is_err());
+        assert!(watcher.wait_for(&Condition::text("never"), Duration::from_millis(20)).is_err());
+        assert!(waiters.list.lock().unwrap().waiters.is_empty());
+        feed(&terminal, &waiters, b"never");
+        assert!(waiters.list.lock().unwrap().waiters.is_empty());
+    }
+
+    #[test]
+    fn test_transient_text_is_seen() {
+        let (watcher, terminal, waiters) = watcher();
+        let handle = std::thread::spawn(move || watcher.wait_for_text("flash", false, Duration::from_secs(5)));
+        while waiters.list.lock().unwrap().waiters.is_empty() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        feed(&terminal, &waiters, b"flash");
+        feed(&terminal, &waiters, b"\r\x1b[K");
+        assert_eq!(handle.join().unwrap().unwrap(), "flash");
+        assert_eq!(terminal.lock().unwrap().screen_text(), "");
+    }
+
+    #[test]
+    fn test_scrollback_search() {
+        let (watcher, terminal, waiters) = watcher();
+        feed(&terminal, &waiters, b"gone\r\n1\r\n2\r\n3\r\n4");
+        assert!(watcher.wait_for_text("gone", false, Duration::from_millis(10)).is_err());
+        assert!(watcher.wait_for_text("gone", true, Duration::from_millis(10)).is_ok());
+    }
+
+    #[test]
+    fn test_wait_for_disappearance() {
+        let (watcher, terminal, waiters) = watcher();
+        feed(&terminal, &waiters, b"Loading...");
+        let condition = Condition { negate: true, ..Condition::text("Loading") };
+        let pending = watcher.clone();
+        let handle = std::thread::spawn(move || pending.wait_for(&condition, Duration::from_secs(5)));
+        while waiters.list.lock().unwrap().waiters.is_empty() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        feed(&terminal, &waiters, b"\r\x1b[Kdone");
+        let (text, result) = handle.join().unwrap().unwrap();
+        assert_eq!(text, "done");
+        assert!(result.matched);
+        assert_eq!(result.count, 0);
+    }
+
+    #[test]
+    fn test_wait_for_regex() {
+        let (watcher, terminal, waiters) = watcher();
+        feed(&terminal, &waiters, b"port 8080");
+        let (_, result) = watcher.wait_for(&Condition::regex(r"port (\d+)"), Duration::from_millis(10)).unwrap();
+        assert_eq!(result.matches[0].groups, vec![Some("8080".to_string())]);
+    }
+
+    #[test]
+    fn test_wait_stable() {
+        let (watcher, terminal, waiters) = watcher();
+        let feeder = std::thread::spawn(move || {
+            for _ in 0..5 {
+                feed(&terminal, &waiters, b".");
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        });
+        let start = Instant::now();
+        watcher.wait_stable(Duration::from_millis(60), Duration::from_secs(5)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        feeder.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_stable_waits_from_start() {
+        let (watcher, terminal, waiters) = watcher();
+        feed(&terminal, &waiters, b".");
+        std::thread::sleep(Duration::from_millis(60));
+        let start = Instant::now();
+        watcher.wait_stable(Duration::from_millis(50), Duration::from_secs(5)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_wait_stable_timeout() {
+        let (watcher, terminal, waiters) = watcher();
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        let feeder = std::thread::spawn(move || {
+            while !flag.load(std::sync::atomic::Ordering::SeqCst) {
+                feed(&terminal, &waiters, b".");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+        let result = watcher.wait_stable(Duration::from_millis(100), Duration::from_millis(150));
+        stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        feeder.join().unwrap();
+        assert!(matches!(result, Err(Error::Timeout(150))));
+    }
+
+    #[test]
+    fn test_close_fails_waiters() {
+        let (watcher, _, waiters) = watcher();
+        let pending = watcher.clone();
+        let handle = std::thread::spawn(move || pending.wait_for_text("x", false, Duration::from_secs(5)));
+        while waiters.list.lock().unwrap().waiters.is_empty() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        waiters.close();
+        assert!(matches!(handle.join().unwrap(), Err(Error::ChildExited)));
+        assert!(matches!(
+            watcher.wait_for_text("x", false, Duration::from_millis(10)),
+            Err(Error::ChildExited)
+        ));
+    }
+}
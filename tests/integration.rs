@@ -2,8 +2,11 @@ use std::thread;
 use std::time::Duration;
 
 use tui_wright::client;
+use tui_wright::condition::Condition;
+use tui_wright::input::{KeyEncoding, KeyEvent, KeyOverride, Modifiers, MouseButton};
 use tui_wright::protocol::{Request, Response};
 use tui_wright::server;
+use tui_wright::session::{Session, SpawnOptions};
 
 fn spawn_bash_session() -> String {
     let session_id = server::generate_session_id();
@@ -17,6 +20,34 @@ fn spawn_bash_session() -> String {
     session_id
 }
 
+// Re-sends `request` until `done` holds for the value `extract` takes from the
+// response, returning the last value after five seconds either way.
+fn poll<T, E, F>(session_id: &str, request: &Request, extract: E, done: F) -> T
+where
+    E: Fn(Response) -> Option<T>,
+    F: Fn(&T) -> bool,
+{
+    let mut value = None;
+    for _ in 0..50 {
+        thread::sleep(Duration::from_millis(100));
+        let response = client::send_request(session_id, request).unwrap();
+        let description = format!("{:?}", response);
+        let current = extract(response).unwrap_or_else(|| panic!("Unexpected response: {}", description));
+        if done(&current) {
+            return current;
+        }
+        value = Some(current);
+    }
+    value.unwrap()
+}
+
+fn text(response: Response) -> Option<String> {
+    match response {
+        Response::Text { text } => Some(text),
+        _ => None,
+    }
+}
+
 fn cleanup(session_id: &str) {
     let _ = client::send_request(session_id, &Request::Kill);
     thread::sleep(Duration::from_millis(100));
@@ -39,8 +70,8 @@ fn test_spawn_and_screen() {
 fn test_type_and_read() {
     let session = spawn_bash_session();
 
-    client::send_request(&session, &Request::Type { text: "echo integration_test_marker".into() }).unwrap();
-    client::send_request(&session, &Request::Key { name: "enter".into() }).unwrap();
+    client::send_request(&session, &Request::type_text("echo integration_test_marker")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
     thread::sleep(Duration::from_millis(300));
 
     let resp = client::send_request(&session, &Request::Screen { json: false }).unwrap();
@@ -72,8 +103,8 @@ fn test_cursor_position() {
 fn test_json_screen() {
     let session = spawn_bash_session();
 
-    client::send_request(&session, &Request::Type { text: "echo json_test".into() }).unwrap();
-    client::send_request(&session, &Request::Key { name: "enter".into() }).unwrap();
+    client::send_request(&session, &Request::type_text("echo json_test")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
     thread::sleep(Duration::from_millis(300));
 
     let resp = client::send_request(&session, &Request::Screen { json: true }).unwrap();
@@ -112,7 +143,7 @@ fn test_resize() {
 #[test]
 fn test_key_arrow() {
     let session = spawn_bash_session();
-    let resp = client::send_request(&session, &Request::Key { name: "up".into() }).unwrap();
+    let resp = client::send_request(&session, &Request::key("up")).unwrap();
     assert!(matches!(resp, Response::Ok));
     cleanup(&session);
 }
@@ -153,8 +184,8 @@ fn test_trace_start_stop() {
     }).unwrap();
     assert!(matches!(resp, Response::Ok));
 
-    client::send_request(&session, &Request::Type { text: "echo trace_test".into() }).unwrap();
-    client::send_request(&session, &Request::Key { name: "enter".into() }).unwrap();
+    client::send_request(&session, &Request::type_text("echo trace_test")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
     thread::sleep(Duration::from_millis(300));
 
     let resp = client::send_request(&session, &Request::TraceMarker {
@@ -200,8 +231,8 @@ fn test_trace_start_stop() {
 fn test_snapshot_diff_identical() {
     let session = spawn_bash_session();
 
-    client::send_request(&session, &Request::Type { text: "echo snapshot_test".into() }).unwrap();
-    client::send_request(&session, &Request::Key { name: "enter".into() }).unwrap();
+    client::send_request(&session, &Request::type_text("echo snapshot_test")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
     thread::sleep(Duration::from_millis(300));
 
     let resp = client::send_request(&session, &Request::Screen { json: true }).unwrap();
@@ -212,6 +243,7 @@ fn test_snapshot_diff_identical() {
 
     let diff_resp = client::send_request(&session, &Request::SnapshotDiff {
         baseline: baseline.clone(),
+        colors: Default::default(),
     }).unwrap();
     match diff_resp {
         Response::Diff { diff } => {
@@ -234,20 +266,819 @@ fn test_snapshot_diff_changed() {
         other => panic!("Expected Screen response, got: {:?}", other),
     };
 
-    client::send_request(&session, &Request::Type { text: "echo changed".into() }).unwrap();
-    client::send_request(&session, &Request::Key { name: "enter".into() }).unwrap();
+    client::send_request(&session, &Request::type_text("echo changed")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
     thread::sleep(Duration::from_millis(300));
 
     let diff_resp = client::send_request(&session, &Request::SnapshotDiff {
         baseline,
+        colors: Default::default(),
     }).unwrap();
     match diff_resp {
         Response::Diff { diff } => {
             assert!(!diff.identical, "Diff should detect changes");
-            assert!(diff.changed_cells.len() > 0, "Should have changed cells");
+            assert!(!diff.changed_cells.is_empty(), "Should have changed cells");
         }
         other => panic!("Expected Diff response, got: {:?}", other),
     }
 
     cleanup(&session);
 }
+
+#[test]
+fn test_cursor_position_query_is_answered() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text(
+        "printf '\\e[6n'; read -rsdR -t 2 pos; echo \"cpr=${pos#*[}\"",
+    )).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+
+    let answered = |screen: &str| {
+        screen.lines().any(|line| {
+            line.rsplit_once("cpr=")
+                .and_then(|(_, pos)| pos.split_once(';'))
+                .is_some_and(|(row, col)| row.parse::<u16>().is_ok() && col.parse::<u16>().is_ok())
+        })
+    };
+    let screen = poll(&session, &Request::Screen { json: false }, text, |screen| answered(screen));
+    assert!(answered(&screen), "Cursor position report should reach the app: {}", screen);
+    cleanup(&session);
+}
+
+#[test]
+fn test_scrollback() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text("seq -f 'row%g' 1 60")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+
+    let history = poll(&session, &Request::Scrollback { json: false }, text, |history| {
+        history.lines().any(|line| line == "row1")
+    });
+    let lines: Vec<&str> = history.lines().collect();
+    let first = lines.iter().position(|line| *line == "row1").expect("row1 should be in scrollback");
+    assert_eq!(lines[first + 1], "row2");
+
+    let resp = client::send_request(&session, &Request::Scrollback { json: true }).unwrap();
+    match resp {
+        Response::Screen { snapshot } => {
+            assert_eq!(snapshot.cells.len(), snapshot.rows as usize);
+            assert_eq!(snapshot.rows as usize, lines.len());
+        }
+        other => panic!("Expected Screen response, got: {:?}", other),
+    }
+    cleanup(&session);
+}
+
+#[test]
+fn test_wait_for() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text("echo wait_''for_marker")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+
+    let resp = client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("wait_for_marker"),
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+    match resp {
+        Response::Text { text } => assert!(text.contains("wait_for_marker")),
+        other => panic!("Expected Text response, got: {:?}", other),
+    }
+
+    let resp = client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("never_printed"),
+        timeout: 200,
+        json: false,
+    }).unwrap();
+    match resp {
+        Response::Error { message } => assert!(message.contains("Timeout"), "{}", message),
+        other => panic!("Expected Error response, got: {:?}", other),
+    }
+    cleanup(&session);
+}
+
+#[test]
+fn test_wait_for_transient_text() {
+    let session = spawn_bash_session();
+
+    let id = session.clone();
+    let waiter = thread::spawn(move || {
+        client::send_request(&id, &Request::WaitFor {
+            condition: Condition::text("transient_marker"),
+            timeout: 8000,
+            json: false,
+        })
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    client::send_request(&session, &Request::type_text("printf 'transient_''marker'; sleep 0.2; clear")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+
+    match waiter.join().unwrap().unwrap() {
+        Response::Text { text } => assert!(text.contains("transient_marker")),
+        other => panic!("Expected Text response, got: {:?}", other),
+    }
+    cleanup(&session);
+}
+
+#[test]
+fn test_wait_stable() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text(
+        "for i in 1 2 3 4 5; do echo tick$i; sleep 0.05; done; echo stable_''done",
+    )).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("tick1"),
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+
+    let resp = client::send_request(&session, &Request::WaitStable { quiet_ms: 300, timeout: 5000 }).unwrap();
+    assert!(matches!(resp, Response::Ok), "{:?}", resp);
+    match client::send_request(&session, &Request::Screen { json: false }).unwrap() {
+        Response::Text { text } => assert!(text.contains("stable_done"), "{}", text),
+        other => panic!("Expected Text response, got: {:?}", other),
+    }
+
+    client::send_request(&session, &Request::type_text("while :; do echo busy; sleep 0.02; done")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let resp = client::send_request(&session, &Request::WaitStable { quiet_ms: 500, timeout: 300 }).unwrap();
+    match resp {
+        Response::Error { message } => assert!(message.contains("Timeout"), "{}", message),
+        other => panic!("Expected Error response, got: {:?}", other),
+    }
+    cleanup(&session);
+}
+
+#[test]
+fn test_wait_stable_after_input() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text("read -rs; sleep 0.3; echo late_''output")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    poll(&session, &Request::ProcessInfo, process_info, |info| info.termios.as_ref().is_some_and(|t| !t.echo));
+    thread::sleep(Duration::from_millis(1000));
+
+    // The silent enter produces no output, so the screen only settles once
+    // the delayed echo has come and gone.
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let resp = client::send_request(&session, &Request::WaitStable { quiet_ms: 800, timeout: 8000 }).unwrap();
+    assert!(matches!(resp, Response::Ok), "{:?}", resp);
+    let screen = text(client::send_request(&session, &Request::Screen { json: false }).unwrap()).unwrap();
+    assert!(screen.contains("late_output"), "{}", screen);
+    cleanup(&session);
+}
+
+#[test]
+fn test_wait_for_and_assert_conditions() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text("echo pid=$((40+2)) pid=$((70+7))")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+
+    let resp = client::send_request(&session, &Request::WaitFor {
+        condition: Condition { count: Some(2), ..Condition::regex(r"pid=(\d+)") },
+        timeout: 5000,
+        json: true,
+    }).unwrap();
+    match resp {
+        Response::Match { result, .. } => {
+            assert!(result.matched);
+            assert_eq!(result.matches[0].groups, vec![Some("42".to_string())]);
+            assert_eq!(result.matches[1].groups, vec![Some("77".to_string())]);
+        }
+        other => panic!("Expected Match response, got: {:?}", other),
+    }
+
+    let resp = client::send_request(&session, &Request::Assert {
+        condition: Condition { negate: true, ..Condition::text("pid=42") },
+    }).unwrap();
+    match resp {
+        Response::Match { text, result } => {
+            assert!(!result.matched);
+            assert!(text.contains("pid=42"));
+        }
+        other => panic!("Expected Match response, got: {:?}", other),
+    }
+
+    client::send_request(&session, &Request::type_text("clear")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let resp = client::send_request(&session, &Request::WaitFor {
+        condition: Condition { negate: true, ..Condition::text("pid=42") },
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+    assert!(matches!(resp, Response::Text { .. }), "{:?}", resp);
+    cleanup(&session);
+}
+
+#[test]
+fn test_find_and_click_text() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text("clear; printf '  [ O''K ]  [ Cancel ]'; read -r line")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("[ OK ]"),
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+
+    match client::send_request(&session, &Request::Find { pattern: "Cancel".into(), regex: false }).unwrap() {
+        Response::Locations { locations } => {
+            assert_eq!(locations.len(), 1);
+            assert_eq!((locations[0].row, locations[0].start_col, locations[0].end_col), (0, 12, 18));
+        }
+        other => panic!("Expected Locations response, got: {:?}", other),
+    }
+
+    let resp = client::send_request(&session, &Request::ClickText { pattern: "OK".into(), regex: false, index: 0 }).unwrap();
+    assert!(matches!(resp, Response::Locations { .. }), "{:?}", resp);
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    client::send_request(&session, &Request::type_text("printf '%q\\n' \"$line\"")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let resp = client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("[<0;5;1M"),
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+    assert!(matches!(resp, Response::Text { .. }), "{:?}", resp);
+
+    let resp = client::send_request(&session, &Request::ClickText { pattern: "Missing".into(), regex: false, index: 0 }).unwrap();
+    assert!(matches!(resp, Response::Error { .. }), "{:?}", resp);
+    cleanup(&session);
+}
+
+#[test]
+fn test_find_style() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text(
+        "clear; printf '  Open\\n\\033[7m> Save\\033[m\\n  Quit\\n'",
+    )).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    client::send_request(&session, &Request::WaitFor {
+        condition: Condition::regex("(?m)^  Quit$"),
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+
+    let query = tui_wright::style::StyleQuery { inverse: Some(true), ..Default::default() };
+    match client::send_request(&session, &Request::FindStyle { query }).unwrap() {
+        Response::Locations { locations } => {
+            assert_eq!(locations.len(), 1, "{:?}", locations);
+            assert_eq!((locations[0].row, locations[0].start_col), (1, 0));
+            assert_eq!(locations[0].text, "> Save");
+        }
+        other => panic!("Expected Locations response, got: {:?}", other),
+    }
+    cleanup(&session);
+}
+
+#[test]
+fn test_exit_status_and_final_screen() {
+    let session = server::generate_session_id();
+    let id = session.clone();
+    let args: Vec<String> = vec!["-c".into(), "echo goodbye; sleep 0.3; exit 3".into()];
+    let cwd = std::env::current_dir().unwrap();
+    thread::spawn(move || {
+        server::run_daemon("sh", &args, 80, 24, &id, &cwd).ok();
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    match client::send_request(&session, &Request::Status).unwrap() {
+        Response::Status { running, pid, exit } => {
+            assert!(running);
+            assert!(pid.is_some());
+            assert!(exit.is_none());
+        }
+        other => panic!("Expected Status response, got: {:?}", other),
+    }
+
+    match client::send_request(&session, &Request::WaitExit { timeout: 5000 }).unwrap() {
+        Response::Status { running, exit, .. } => {
+            assert!(!running);
+            let exit = exit.unwrap();
+            assert_eq!(exit.code, 3);
+            assert!(exit.signal.is_none());
+        }
+        other => panic!("Expected Status response, got: {:?}", other),
+    }
+
+    match client::send_request(&session, &Request::Screen { json: false }).unwrap() {
+        Response::Text { text } => assert!(text.contains("goodbye"), "{}", text),
+        other => panic!("Expected Text response, got: {:?}", other),
+    }
+    let resp = client::send_request(&session, &Request::type_text("x")).unwrap();
+    assert!(matches!(resp, Response::Error { .. }), "{:?}", resp);
+
+    let resp = client::send_request(&session, &Request::Kill).unwrap();
+    assert!(matches!(resp, Response::Ok));
+    thread::sleep(Duration::from_millis(100));
+    assert!(client::send_request(&session, &Request::Status).is_err());
+}
+
+#[test]
+fn test_daemon_exits_after_linger() {
+    let session = server::generate_session_id();
+    let id = session.clone();
+    let args: Vec<String> = vec!["-c".into(), "exit 0".into()];
+    let cwd = std::env::current_dir().unwrap();
+    let options = SpawnOptions { linger: Duration::from_millis(300), ..SpawnOptions::default() };
+    let daemon = thread::spawn(move || server::run_daemon_with_options("sh", &args, 80, 24, &id, &cwd, options));
+    thread::sleep(Duration::from_millis(100));
+
+    match client::send_request(&session, &Request::WaitExit { timeout: 5000 }).unwrap() {
+        Response::Status { running, .. } => assert!(!running),
+        other => panic!("Expected Status response, got: {:?}", other),
+    }
+    assert!(daemon.join().unwrap().is_ok());
+    assert!(!server::socket_path(&session).exists());
+}
+
+#[test]
+fn test_hermetic_environment() {
+    let session = server::generate_session_id();
+    let id = session.clone();
+    let script = "printf 'env=%s|%s|%s|%s|%s\\n' \"$TERM\" \"$LANG\" \"$TZ\" \"$FOO\" \"$COLORTERM\"; \
+                  printf 'home=%s\\n' \"$HOME\"; \
+                  printf 'extra=%s\\n' \"$(env | grep -c -v -E '^(PATH|HOME|SHELL|TERM|COLORTERM|LANG|LC_ALL|TZ|FOO|PWD|SHLVL|_|OLDPWD)=')\"; \
+                  sleep 5";
+    let args: Vec<String> = vec!["-c".into(), script.into()];
+    let cwd = std::env::current_dir().unwrap();
+    let options = SpawnOptions {
+        hermetic: true,
+        colorterm: Some("truecolor".into()),
+        env: vec![("FOO".into(), "bar".into())],
+        ..SpawnOptions::default()
+    };
+    thread::spawn(move || {
+        server::run_daemon_with_options("sh", &args, 80, 24, &id, &cwd, options).ok();
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    let text = match client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("extra="),
+        timeout: 5000,
+        json: false,
+    }).unwrap() {
+        Response::Text { text } => text,
+        other => panic!("Expected Text response, got: {:?}", other),
+    };
+    assert!(text.contains("env=xterm-256color|C.UTF-8|UTC|bar|truecolor"), "{}", text);
+    assert!(text.contains("extra=0"), "{}", text);
+    let home = text.lines().find_map(|l| l.strip_prefix("home=")).unwrap().to_string();
+    assert_ne!(Some(home.as_str()), std::env::var("HOME").ok().as_deref());
+    assert!(std::path::Path::new(&home).is_dir());
+
+    cleanup(&session);
+    assert!(!std::path::Path::new(&home).exists());
+}
+
+#[test]
+fn test_signal() {
+    let session = server::generate_session_id();
+    let id = session.clone();
+    let script = "trap 'echo got_usr1' USR1; echo ready; while :; do sleep 0.1; done";
+    let args: Vec<String> = vec!["-c".into(), script.into()];
+    let cwd = std::env::current_dir().unwrap();
+    thread::spawn(move || {
+        server::run_daemon("sh", &args, 80, 24, &id, &cwd).ok();
+    });
+    thread::sleep(Duration::from_millis(100));
+    client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("ready"),
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+
+    let resp = client::send_request(&session, &Request::Signal { name: "SIGUSR1".into() }).unwrap();
+    assert!(matches!(resp, Response::Ok), "{:?}", resp);
+    let resp = client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("got_usr1"),
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+    assert!(matches!(resp, Response::Text { .. }), "{:?}", resp);
+
+    let resp = client::send_request(&session, &Request::Signal { name: "bogus".into() }).unwrap();
+    assert!(matches!(resp, Response::Error { .. }), "{:?}", resp);
+
+    client::send_request(&session, &Request::Signal { name: "term".into() }).unwrap();
+    match client::send_request(&session, &Request::WaitExit { timeout: 5000 }).unwrap() {
+        Response::Status { exit: Some(exit), .. } => assert!(exit.signal.is_some(), "{:?}", exit),
+        other => panic!("Expected Status response, got: {:?}", other),
+    }
+    cleanup(&session);
+}
+
+#[test]
+fn test_teardown_kills_process_tree() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text(
+        "sleep 1000 & sh -c \"trap '' TERM HUP; echo stub''born; exec sleep 1001\" &",
+    )).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    client::send_request(&session, &Request::WaitFor {
+        condition: Condition::text("stubborn"),
+        timeout: 5000,
+        json: false,
+    }).unwrap();
+
+    let report = match client::send_request(&session, &Request::Teardown { grace_ms: Some(300) }).unwrap() {
+        Response::Teardown { report } => report,
+        other => panic!("Expected Teardown response, got: {:?}", other),
+    };
+    assert!(report.terminated.iter().any(|p| p.command.contains("bash")), "{:?}", report);
+    assert!(report.terminated.iter().any(|p| p.command == "sleep 1000"), "{:?}", report);
+    let stubborn = report.killed.iter().find(|p| p.command == "sleep 1001").expect("stubborn sleep force-killed");
+
+    thread::sleep(Duration::from_millis(100));
+    let alive = std::fs::read_to_string(format!("/proc/{}/stat", stubborn.pid))
+        .map(|stat| !stat.rsplit_once(')').unwrap().1.trim_start().starts_with('Z'))
+        .unwrap_or(false);
+    assert!(!alive, "pid {} survived teardown", stubborn.pid);
+    assert!(client::send_request(&session, &Request::Status).is_err());
+}
+
+#[test]
+fn test_session_teardown_runs_once() {
+    // The child must not depend on the thread that spawned it.
+    let cwd = std::env::current_dir().unwrap();
+    let mut session = thread::spawn(move || Session::spawn("sleep", &["30".to_string()], 80, 24, &cwd).unwrap())
+        .join()
+        .unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert!(session.is_alive());
+
+    let report = session.teardown(Duration::from_millis(500));
+    assert!(report.terminated.iter().any(|p| p.command == "sleep 30"), "{:?}", report);
+    assert!(session.wait_exit(Duration::from_secs(5)).is_ok());
+    assert_eq!(session.teardown(Duration::from_millis(500)), Default::default());
+}
+
+fn process_info(response: Response) -> Option<tui_wright::process::ProcessInfo> {
+    match response {
+        Response::ProcessInfo { info } => Some(info),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_process_info() {
+    let session = spawn_bash_session();
+
+    client::send_request(&session, &Request::type_text("sleep 30")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let info = poll(&session, &Request::ProcessInfo, process_info, |info| info.foreground_command.as_deref() == Some("sleep 30"));
+    assert_eq!(info.foreground_command.as_deref(), Some("sleep 30"));
+    assert_ne!(info.foreground_pgrp, info.pid);
+    let termios = info.termios.unwrap();
+    assert!(termios.canonical && termios.echo);
+
+    client::send_request(&session, &Request::key("ctrl+c")).unwrap();
+    client::send_request(&session, &Request::type_text("read -rs secret")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let info = poll(&session, &Request::ProcessInfo, process_info, |info| {
+        info.foreground_pgrp == info.pid && info.termios.as_ref().is_some_and(|t| t.canonical && !t.echo)
+    });
+    assert_eq!(info.foreground_pgrp, info.pid);
+    assert!(!info.termios.unwrap().echo, "password prompt should disable echo");
+    cleanup(&session);
+}
+
+fn terminal_modes(response: Response) -> Option<tui_wright::screen::TerminalModes> {
+    match response {
+        Response::Modes { modes } => Some(modes),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_terminal_modes() {
+    let session = spawn_bash_session();
+
+    let enable = r"printf '\e[?1049h\e[?1000h\e[?1006h\e[?25l'";
+    client::send_request(&session, &Request::type_text(enable)).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let modes = poll(&session, &Request::Modes, terminal_modes, |m| m.alternate_screen);
+    assert!(modes.alternate_screen && !modes.cursor_visible);
+    assert_eq!(modes.mouse_mode, tui_wright::screen::MouseMode::PressRelease);
+    assert_eq!(modes.mouse_encoding, tui_wright::screen::MouseEncoding::Sgr);
+
+    match client::send_request(&session, &Request::Screen { json: true }).unwrap() {
+        Response::Screen { snapshot } => assert_eq!(snapshot.modes, modes),
+        other => panic!("Expected Screen response, got: {:?}", other),
+    }
+
+    let restore = r"printf '\e[?1000l\e[?25h\e[?1049l'";
+    client::send_request(&session, &Request::type_text(restore)).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let modes = poll(&session, &Request::Modes, terminal_modes, |m| !m.alternate_screen);
+    assert!(!modes.alternate_screen && modes.cursor_visible);
+    assert_eq!(modes.mouse_mode, tui_wright::screen::MouseMode::None);
+    cleanup(&session);
+}
+
+#[test]
+fn test_key_follows_application_cursor_mode() {
+    let session = spawn_bash_session();
+    let read_key = r"printf '\e[?1h'; read -rsn3 k; printf '%q\n' $k; read -rsn3 k; printf '%q\n' $k";
+    client::send_request(&session, &Request::type_text(read_key)).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    poll(&session, &Request::Modes, terminal_modes, |m| m.application_cursor);
+
+    client::send_request(&session, &Request::key("up")).unwrap();
+    let wait = Request::WaitFor { condition: Condition::text(r"$'\EOA'"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    let normal = KeyOverride { cursor: Some(KeyEncoding::Normal), ..Default::default() };
+    client::send_request(&session, &Request::Key { name: "up".into(), event: KeyEvent::Press, encoding: normal }).unwrap();
+    let wait = Request::WaitFor { condition: Condition::text(r"$'\E[A'"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+    cleanup(&session);
+}
+
+#[test]
+fn test_modified_keys() {
+    let session = spawn_bash_session();
+    let read_keys = r"read -rsn6 k; printf '%q\n' $k; read -rsn3 k; printf '%q\n' $k";
+    client::send_request(&session, &Request::type_text(read_keys)).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    thread::sleep(Duration::from_millis(300));
+
+    client::send_request(&session, &Request::key("ctrl+up")).unwrap();
+    let wait = Request::WaitFor { condition: Condition::text(r"$'\E[1;5A'"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    client::send_request(&session, &Request::key("shift+tab")).unwrap();
+    let wait = Request::WaitFor { condition: Condition::text(r"$'\E[Z'"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+    cleanup(&session);
+}
+
+#[test]
+fn test_kitty_keyboard_protocol() {
+    let session = spawn_bash_session();
+    let read_keys = r"printf '\e[>3u'; read -rsn8 k; printf '%q\n' $k; read -rsn10 k; printf '%q\n' $k";
+    client::send_request(&session, &Request::type_text(read_keys)).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let modes = poll(&session, &Request::Modes, terminal_modes, |m| m.keyboard_flags != 0);
+    assert_eq!(modes.keyboard_flags, 3);
+
+    client::send_request(&session, &Request::key("ctrl+i")).unwrap();
+    let wait = Request::WaitFor { condition: Condition::text(r"$'\E[105;5u'"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    client::send_request(&session, &Request::Key { name: "ctrl+i".into(), event: KeyEvent::Release, encoding: KeyOverride::default() }).unwrap();
+    let wait = Request::WaitFor { condition: Condition::text(r"$'\E[105;5:3u'"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+    cleanup(&session);
+}
+
+#[test]
+fn test_paste() {
+    let session = spawn_bash_session();
+    let cast_file = std::env::temp_dir().join(format!("test-paste-{}.cast", &session));
+    client::send_request(&session, &Request::type_text("cat -v")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    client::send_request(&session, &Request::TraceStart { output: Some(cast_file.to_string_lossy().to_string()) }).unwrap();
+
+    // cat has not enabled bracketed paste, so only the forced paste is wrapped.
+    let resp = client::send_request(&session, &Request::Paste { text: "plain one\nplain two\n".into(), force: false }).unwrap();
+    assert!(matches!(resp, Response::Ok));
+    let wait = Request::WaitFor { condition: Condition::regex("(?m)^plain two$"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    client::send_request(&session, &Request::Paste { text: "first\nsecond".into(), force: true }).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let wait = Request::WaitFor { condition: Condition::text("second^[[201~"), timeout: 5000, json: false };
+    match client::send_request(&session, &wait).unwrap() {
+        Response::Text { text } => {
+            assert!(text.contains("^[[200~first"));
+            assert!(!text.contains("^[[200~plain"));
+        }
+        other => panic!("Expected Text response, got: {:?}", other),
+    }
+
+    client::send_request(&session, &Request::TraceStop).unwrap();
+    let content = std::fs::read_to_string(&cast_file).unwrap();
+    let markers: Vec<serde_json::Value> = content
+        .lines()
+        .skip(1)
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|event| event[1] == "m")
+        .collect();
+    assert_eq!(markers[0][2], r#"paste "plain one\nplain two\n""#);
+    assert_eq!(markers[1][2], r#"paste "first\nsecond""#);
+    let _ = std::fs::remove_file(&cast_file);
+    cleanup(&session);
+}
+
+#[test]
+fn test_mouse_drag() {
+    let session = spawn_bash_session();
+    client::send_request(&session, &Request::type_text(r"printf '\e[?1002h\e[?1006h'; cat -v")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    poll(&session, &Request::Modes, terminal_modes, |m| {
+        m.mouse_mode == tui_wright::screen::MouseMode::ButtonMotion
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    let drag = Request::Drag { from: (1, 1), to: (3, 1), button: MouseButton::Left, modifiers: Modifiers::default() };
+    assert!(matches!(client::send_request(&session, &drag).unwrap(), Response::Ok));
+    let wait = Request::WaitFor { condition: Condition::text("^[[<0;2;2M^[[<32;3;2M^[[<32;4;2M^[[<0;4;2m"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    let click = Request::Mouse { action: "press".into(), col: 0, row: 0, button: MouseButton::Right, modifiers: Modifiers::CTRL, clicks: None, interval_ms: None };
+    client::send_request(&session, &click).unwrap();
+    let wait = Request::WaitFor { condition: Condition::text("^[[<18;1;1M"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+    cleanup(&session);
+}
+
+#[test]
+fn test_mouse_double_click() {
+    let session = spawn_bash_session();
+    client::send_request(&session, &Request::type_text(r"printf '\e[?1000h\e[?1006h'; cat -v")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    poll(&session, &Request::Modes, terminal_modes, |m| m.mouse_mode == tui_wright::screen::MouseMode::PressRelease);
+    thread::sleep(Duration::from_millis(200));
+
+    let started = std::time::Instant::now();
+    let click = Request::Mouse {
+        action: "click".into(),
+        col: 4,
+        row: 2,
+        button: MouseButton::Left,
+        modifiers: Modifiers::default(),
+        clicks: Some(2),
+        interval_ms: Some(150),
+    };
+    assert!(matches!(client::send_request(&session, &click).unwrap(), Response::Ok));
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    let wait = Request::WaitFor { condition: Condition::text("^[[<0;5;3M^[[<0;5;3m^[[<0;5;3M^[[<0;5;3m"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    let none = Request::Mouse {
+        action: "click".into(),
+        col: 4,
+        row: 2,
+        button: MouseButton::Left,
+        modifiers: Modifiers::default(),
+        clicks: Some(0),
+        interval_ms: None,
+    };
+    assert!(matches!(client::send_request(&session, &none).unwrap(), Response::Error { message } if message.starts_with("Invalid argument")));
+    let interval_only = Request::Mouse {
+        action: "press".into(),
+        col: 4,
+        row: 2,
+        button: MouseButton::Left,
+        modifiers: Modifiers::default(),
+        clicks: None,
+        interval_ms: Some(100),
+    };
+    assert!(matches!(client::send_request(&session, &interval_only).unwrap(), Response::Error { message } if message.starts_with("Invalid argument")));
+    let scroll = Request::Mouse {
+        action: "scrollup".into(),
+        col: 4,
+        row: 2,
+        button: MouseButton::Left,
+        modifiers: Modifiers::default(),
+        clicks: Some(2),
+        interval_ms: None,
+    };
+    assert!(matches!(client::send_request(&session, &scroll).unwrap(), Response::Error { .. }));
+    cleanup(&session);
+}
+
+#[test]
+fn test_focus_events() {
+    let session = spawn_bash_session();
+    client::send_request(&session, &Request::type_text("cat -v")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    // Dropped, as the app has not asked for focus events, unless forced.
+    let focus = |gained, force| client::send_request(&session, &Request::Focus { gained, force }).unwrap();
+    assert!(matches!(focus(false, false), Response::Ok));
+    assert!(matches!(focus(true, true), Response::Ok));
+    let wait = Request::WaitFor { condition: Condition::text("^[[I"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+    assert!(!text(client::send_request(&session, &Request::Screen { json: false }).unwrap()).unwrap().contains("^[[O"));
+    client::send_request(&session, &Request::key("ctrl+c")).unwrap();
+
+    client::send_request(&session, &Request::type_text(r"printf '\e[?1004h'; cat -v")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    poll(&session, &Request::Modes, terminal_modes, |m| m.focus_events);
+    thread::sleep(Duration::from_millis(200));
+
+    assert!(matches!(focus(false, false), Response::Ok));
+    assert!(matches!(focus(true, false), Response::Ok));
+    let wait = Request::WaitFor { condition: Condition::text("^[[O^[[I"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+    cleanup(&session);
+}
+
+#[test]
+fn test_paced_typing() {
+    let session = spawn_bash_session();
+    let cast_file = std::env::temp_dir().join(format!("test-paced-{}.cast", &session));
+    client::send_request(&session, &Request::TraceStart { output: Some(cast_file.to_string_lossy().to_string()) }).unwrap();
+
+    let started = std::time::Instant::now();
+    let text = "echo a\u{e9}e\u{301}";
+    let resp = client::send_request(&session, &Request::Type { text: text.into(), delay_ms: 30, jitter_ms: 20 }).unwrap();
+    assert!(matches!(resp, Response::Ok));
+    assert!(started.elapsed() >= Duration::from_millis(30 * 7));
+    let wait = Request::WaitFor { condition: Condition::text(text), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    client::send_request(&session, &Request::TraceStop).unwrap();
+    let content = std::fs::read_to_string(&cast_file).unwrap();
+    let keystrokes: Vec<String> = content
+        .lines()
+        .skip(1)
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|event| event[1] == "i")
+        .map(|event| event[2].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(keystrokes, ["e", "c", "h", "o", " ", "a", "\u{e9}", "e\u{301}"]);
+    let _ = std::fs::remove_file(&cast_file);
+
+    // Other requests are answered while paced input is still being sent.
+    let typing = {
+        let session = session.clone();
+        thread::spawn(move || {
+            let slow = Request::Type { text: "xyz".into(), delay_ms: 500, jitter_ms: 0 };
+            client::send_request(&session, &slow).unwrap()
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+    let started = std::time::Instant::now();
+    assert!(matches!(client::send_request(&session, &Request::Cursor).unwrap(), Response::Cursor { .. }));
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert!(matches!(typing.join().unwrap(), Response::Ok));
+
+    // Two paced requests at once are sent one after the other, not mixed.
+    let typists: Vec<_> = ["1111", "2222"]
+        .into_iter()
+        .map(|text| {
+            let session = session.clone();
+            thread::spawn(move || {
+                let paced = Request::Type { text: text.into(), delay_ms: 40, jitter_ms: 0 };
+                client::send_request(&session, &paced).unwrap()
+            })
+        })
+        .collect();
+    for typist in typists {
+        assert!(matches!(typist.join().unwrap(), Response::Ok));
+    }
+    let wait = Request::WaitFor { condition: Condition::regex("xyz(11112222|22221111)"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+    cleanup(&session);
+}
+
+#[test]
+fn test_key_sequence() {
+    let session = spawn_bash_session();
+    client::send_request(&session, &Request::type_text(r"read -rsn7 k; printf '%q\n' $k")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    thread::sleep(Duration::from_millis(300));
+
+    let resp = client::send_request(&session, &Request::Keys { sequence: r#"ctrl+a ctrl+b "xy" left"#.into(), delay_ms: 0 }).unwrap();
+    assert!(matches!(resp, Response::Ok));
+    let wait = Request::WaitFor { condition: Condition::text(r"$'\001\002xy\E[D'"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    client::send_request(&session, &Request::type_text("clear")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    let started = std::time::Instant::now();
+    let keys = Request::Keys { sequence: r#""echo" space "'paced  keys'" enter"#.into(), delay_ms: 50 };
+    assert!(matches!(client::send_request(&session, &keys).unwrap(), Response::Ok));
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    let wait = Request::WaitFor { condition: Condition::regex("(?m)^paced  keys$"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+
+    let typo = Request::Keys { sequence: "ctrl+x entr".into(), delay_ms: 0 };
+    assert!(matches!(client::send_request(&session, &typo).unwrap(), Response::Error { .. }));
+    // Ctrl+1 has no legacy encoding, so nothing of the sequence is sent.
+    let unencodable = Request::Keys { sequence: "a ctrl+1".into(), delay_ms: 0 };
+    assert!(matches!(client::send_request(&session, &unencodable).unwrap(), Response::Error { .. }));
+    let unencodable = Request::Keys { sequence: "a ctrl+1".into(), delay_ms: 50 };
+    assert!(matches!(client::send_request(&session, &unencodable).unwrap(), Response::Error { .. }));
+    client::send_request(&session, &Request::type_text("echo nothing''_sent")).unwrap();
+    client::send_request(&session, &Request::key("enter")).unwrap();
+    let wait = Request::WaitFor { condition: Condition::regex("(?m)^nothing_sent$"), timeout: 5000, json: false };
+    assert!(matches!(client::send_request(&session, &wait).unwrap(), Response::Text { .. }));
+    cleanup(&session);
+}